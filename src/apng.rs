@@ -0,0 +1,397 @@
+//
+// author: xigang zhao
+//
+
+// Animated PNG structure: acTL, fcTL and fdAT chunks and the frames they form.
+
+use std::convert::TryFrom;
+use std::str::FromStr;
+use crate::apng_error::ApngError;
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::ImageHeader;
+use crate::png::Png;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnimationControl {
+    num_frames: u32,
+    num_plays : u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameControl {
+    sequence_number: u32,
+    width          : u32,
+    height         : u32,
+    x_offset       : u32,
+    y_offset       : u32,
+    delay_num      : u16,
+    delay_den      : u16,
+    dispose_op     : u8,
+    blend_op       : u8,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct FrameData {
+    sequence_number: u32,
+    data           : Vec<u8>,
+}
+
+// A frame of the animation: its fcTL and the IDAT or fdAT chunks that follow,
+// given as indices into the chunk list.
+#[derive(Debug)]
+pub struct Frame {
+    control: FrameControl,
+    chunks : Vec<usize>,
+}
+
+impl TryFrom<&Chunk> for AnimationControl {
+    type Error = ApngError;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        if &chunk.chunk_type().bytes() != b"acTL" {
+            return Err(ApngError::InvalidChunkType);
+        }
+        let data = chunk.data();
+        if data.len() != AnimationControl::SIZE {
+            return Err(ApngError::InvalidLength);
+        }
+        return Ok(AnimationControl {
+            num_frames: read_u32(&data[0..4]),
+            num_plays : read_u32(&data[4..8]),
+        });
+    }
+}
+
+impl AnimationControl {
+    pub const SIZE: usize = 8;
+
+    pub fn num_frames(&self) -> u32 {
+        return self.num_frames;
+    }
+
+    pub fn num_plays(&self) -> u32 {
+        return self.num_plays;
+    }
+}
+
+impl TryFrom<&Chunk> for FrameControl {
+    type Error = ApngError;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        if &chunk.chunk_type().bytes() != b"fcTL" {
+            return Err(ApngError::InvalidChunkType);
+        }
+        let data = chunk.data();
+        if data.len() != FrameControl::SIZE {
+            return Err(ApngError::InvalidLength);
+        }
+        let fctl = FrameControl {
+            sequence_number: read_u32(&data[0..4]),
+            width          : read_u32(&data[4..8]),
+            height         : read_u32(&data[8..12]),
+            x_offset       : read_u32(&data[12..16]),
+            y_offset       : read_u32(&data[16..20]),
+            delay_num      : u16::from_be_bytes([data[20], data[21]]),
+            delay_den      : u16::from_be_bytes([data[22], data[23]]),
+            dispose_op     : data[24],
+            blend_op       : data[25],
+        };
+        if fctl.dispose_op > FrameControl::DISPOSE_OP_PREVIOUS || fctl.blend_op > FrameControl::BLEND_OP_OVER {
+            return Err(ApngError::InvalidOp);
+        }
+        return Ok(fctl);
+    }
+}
+
+impl FrameControl {
+    pub const SIZE: usize = 26;
+    pub const DISPOSE_OP_PREVIOUS: u8 = 2;
+    pub const BLEND_OP_OVER:       u8 = 1;
+
+    pub fn width(&self) -> u32 {
+        return self.width;
+    }
+
+    pub fn height(&self) -> u32 {
+        return self.height;
+    }
+
+    pub fn x_offset(&self) -> u32 {
+        return self.x_offset;
+    }
+
+    pub fn y_offset(&self) -> u32 {
+        return self.y_offset;
+    }
+
+    pub fn delay(&self) -> (u16, u16) {
+        return (self.delay_num, self.delay_den);
+    }
+
+    pub fn dispose_op(&self) -> u8 {
+        return self.dispose_op;
+    }
+
+    pub fn blend_op(&self) -> u8 {
+        return self.blend_op;
+    }
+
+    pub fn set_delay(&mut self, num: u16, den: u16) {
+        self.delay_num = num;
+        self.delay_den = den;
+    }
+
+    pub fn set_dispose_op(&mut self, op: u8) {
+        self.dispose_op = op;
+    }
+
+    pub fn set_blend_op(&mut self, op: u8) {
+        self.blend_op = op;
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data: Vec<u8> = Vec::with_capacity(FrameControl::SIZE);
+        data.extend_from_slice(&self.sequence_number.to_be_bytes());
+        data.extend_from_slice(&self.width.to_be_bytes());
+        data.extend_from_slice(&self.height.to_be_bytes());
+        data.extend_from_slice(&self.x_offset.to_be_bytes());
+        data.extend_from_slice(&self.y_offset.to_be_bytes());
+        data.extend_from_slice(&self.delay_num.to_be_bytes());
+        data.extend_from_slice(&self.delay_den.to_be_bytes());
+        data.push(self.dispose_op);
+        data.push(self.blend_op);
+        return Chunk::new(ChunkType::from_str("fcTL").unwrap(), data);
+    }
+}
+
+impl TryFrom<&Chunk> for FrameData {
+    type Error = ApngError;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        if &chunk.chunk_type().bytes() != b"fdAT" {
+            return Err(ApngError::InvalidChunkType);
+        }
+        if chunk.data().len() < 4 {
+            return Err(ApngError::InvalidLength);
+        }
+        let (seq, data) = chunk.data().split_at(4);
+        return Ok(FrameData { sequence_number: read_u32(seq), data: data.to_vec() });
+    }
+}
+
+impl FrameData {
+    pub fn data(&self) -> &[u8] {
+        return &self.data;
+    }
+}
+
+impl Frame {
+    pub fn control(&self) -> &FrameControl {
+        return &self.control;
+    }
+
+    pub fn chunks(&self) -> &[usize] {
+        return &self.chunks;
+    }
+}
+
+pub fn is_animated(png: &Png) -> bool {
+    return png.chunk_by_type("acTL").is_some();
+}
+
+pub fn animation_control(png: &Png) -> Result<Option<AnimationControl>, ApngError> {
+    return png.chunk_by_type("acTL").map(AnimationControl::try_from).transpose();
+}
+
+pub fn frames(png: &Png) -> Result<Vec<Frame>, ApngError> {
+    let mut frames: Vec<Frame> = Vec::new();
+    for (i, ck) in png.chunks().iter().enumerate() {
+        match &ck.chunk_type().bytes() {
+            b"fcTL" => frames.push(Frame { control: FrameControl::try_from(ck)?, chunks: vec![i] }),
+            b"IDAT" | b"fdAT" => {
+                if let Some(frame) = frames.last_mut() {
+                    frame.chunks.push(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    return Ok(frames);
+}
+
+// Checks the acTL placement, the frame count and that fcTL and fdAT sequence
+// numbers run 0, 1, 2, ... without gaps.
+pub fn validate(png: &Png) -> Result<(), ApngError> {
+    let header = png.chunk_by_type("IHDR").and_then(|ck| ImageHeader::try_from(ck).ok());
+    let mut actl: Option<AnimationControl> = None;
+    let mut seen_idat = false;
+    let mut in_frame = false;
+    let mut expected: u32 = 0;
+    let mut num_fctl: u32 = 0;
+    for ck in png.chunks() {
+        match &ck.chunk_type().bytes() {
+            b"acTL" => {
+                if seen_idat {
+                    return Err(ApngError::LateAnimationControl);
+                }
+                actl = Some(AnimationControl::try_from(ck)?);
+            }
+            b"IDAT" => seen_idat = true,
+            b"fcTL" => {
+                let fctl = FrameControl::try_from(ck)?;
+                if actl.is_none() {
+                    return Err(ApngError::MissingAnimationControl);
+                }
+                if fctl.sequence_number != expected {
+                    return Err(ApngError::SequenceOutOfOrder(expected, fctl.sequence_number));
+                }
+                if let Some(header) = header {
+                    let fits = fctl.width > 0 && fctl.height > 0
+                               && fctl.x_offset as u64 + fctl.width as u64 <= header.width() as u64
+                               && fctl.y_offset as u64 + fctl.height as u64 <= header.height() as u64;
+                    if !fits {
+                        return Err(ApngError::InvalidFrameSize);
+                    }
+                }
+                expected += 1;
+                num_fctl += 1;
+                in_frame = true;
+            }
+            b"fdAT" => {
+                let fdat = FrameData::try_from(ck)?;
+                if !in_frame {
+                    return Err(ApngError::MissingFrameControl);
+                }
+                if fdat.sequence_number != expected {
+                    return Err(ApngError::SequenceOutOfOrder(expected, fdat.sequence_number));
+                }
+                expected += 1;
+            }
+            _ => {}
+        }
+    }
+    if let Some(actl) = actl && actl.num_frames != num_fctl {
+        return Err(ApngError::FrameCountMismatch);
+    }
+    return Ok(());
+}
+
+// Numbers the fcTL and fdAT chunks 0, 1, 2, ... in chunk order again, after
+// frame data chunks were added or removed.
+pub fn renumber(png: &mut Png) {
+    let mut next: u32 = 0;
+    for i in 0..png.chunks().len() {
+        let ck = &png.chunks()[i];
+        if matches!(&ck.chunk_type().bytes(), b"fcTL" | b"fdAT") && ck.data().len() >= 4 {
+            let mut data = ck.data().to_vec();
+            data[..4].copy_from_slice(&next.to_be_bytes());
+            png.set_chunk(i, Chunk::new(*ck.chunk_type(), data));
+            next += 1;
+        }
+    }
+}
+
+// Index where an extra ancillary chunk can go without landing between a
+// frame's fcTL and its data, or after IEND: right after the last frame.
+pub fn hidden_chunk_position(png: &Png) -> usize {
+    let chunks = png.chunks();
+    let last_data = chunks.iter().rposition(|ck| matches!(&ck.chunk_type().bytes(), b"IDAT" | b"fdAT"));
+    return match last_data {
+        Some(last) => last + 1,
+        None => chunks.iter().position(|ck| &ck.chunk_type().bytes() == b"IEND").unwrap_or(chunks.len()),
+    };
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    return u32::from_be_bytes(bytes.try_into().unwrap());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(chunk_type: &str, data: Vec<u8>) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+    }
+
+    fn fctl(seq: u32) -> Chunk {
+        FrameControl {
+            sequence_number: seq, width: 4, height: 4, x_offset: 0, y_offset: 0,
+            delay_num: 1, delay_den: 10, dispose_op: 0, blend_op: 0,
+        }.to_chunk()
+    }
+
+    fn fdat(seq: u32) -> Chunk {
+        let mut data = seq.to_be_bytes().to_vec();
+        data.extend_from_slice(&[1, 2, 3]);
+        chunk("fdAT", data)
+    }
+
+    // two-frame animation whose first frame is the default image.
+    pub fn testing_apng() -> Png {
+        let mut ihdr = 4u32.to_be_bytes().to_vec();
+        ihdr.extend_from_slice(&4u32.to_be_bytes());
+        ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+        let mut actl = 2u32.to_be_bytes().to_vec();
+        actl.extend_from_slice(&0u32.to_be_bytes());
+        Png::from_chunks(vec![
+            chunk("IHDR", ihdr),
+            chunk("acTL", actl),
+            fctl(0),
+            chunk("IDAT", vec![1, 2, 3]),
+            fctl(1),
+            fdat(2),
+            chunk("IEND", vec![]),
+        ])
+    }
+
+    #[test]
+    fn test_frame_control_round_trip() {
+        let ck = fctl(7);
+        let parsed = FrameControl::try_from(&ck).unwrap();
+        assert_eq!(parsed.sequence_number, 7);
+        assert_eq!(parsed.delay(), (1, 10));
+        assert_eq!(parsed.to_chunk(), ck);
+    }
+
+    #[test]
+    fn test_frames() {
+        let png = testing_apng();
+        assert!(is_animated(&png));
+        assert_eq!(animation_control(&png).unwrap().unwrap().num_frames(), 2);
+        let frames = frames(&png).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].chunks(), &[2, 3]);
+        assert_eq!(frames[1].chunks(), &[4, 5]);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(&testing_apng()).is_ok());
+    }
+
+    #[test]
+    fn test_validate_sequence_gap() {
+        let mut png = testing_apng();
+        png.remove_first_chunk("fdAT").unwrap();
+        png.insert_chunk(5, fdat(3));
+        assert!(matches!(validate(&png), Err(ApngError::SequenceOutOfOrder(2, 3))));
+    }
+
+    #[test]
+    fn test_renumber() {
+        let mut png = testing_apng();
+        png.insert_chunk(6, fdat(9));
+        png.remove_chunk(4);
+        png.insert_chunk(4, fctl(5));
+        renumber(&mut png);
+        assert!(validate(&png).is_ok());
+    }
+
+    #[test]
+    fn test_hidden_chunk_keeps_sequence() {
+        let mut png = testing_apng();
+        let pos = hidden_chunk_position(&png);
+        png.insert_chunk(pos, chunk("ruSt", b"hidden".to_vec()));
+        assert_eq!(png.chunks()[pos + 1].chunk_type().to_string(), "IEND");
+        assert!(validate(&png).is_ok());
+    }
+}
@@ -0,0 +1,37 @@
+//
+// author: xigang zhao
+//
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum ApngError {
+    InvalidLength,
+    InvalidChunkType,
+    InvalidFrameSize,
+    InvalidOp,
+    MissingAnimationControl,
+    LateAnimationControl,
+    FrameCountMismatch,
+    SequenceOutOfOrder(u32, u32),
+    MissingFrameControl,
+}
+
+impl fmt::Display for ApngError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ApngError::InvalidLength           => write!(f, "Invalid length of animation chunk"),
+            ApngError::InvalidChunkType        => write!(f, "Not an animation chunk"),
+            ApngError::InvalidFrameSize        => write!(f, "Frame does not fit within the image"),
+            ApngError::InvalidOp               => write!(f, "Invalid dispose or blend operation"),
+            ApngError::MissingAnimationControl => write!(f, "Frame chunks found without acTL"),
+            ApngError::LateAnimationControl    => write!(f, "acTL must come before the first IDAT"),
+            ApngError::FrameCountMismatch      => write!(f, "acTL frame count does not match the fcTL chunks"),
+            ApngError::SequenceOutOfOrder(expected, found) =>
+                write!(f, "Sequence number {} found where {} was expected", found, expected),
+            ApngError::MissingFrameControl     => write!(f, "Frame data found without a preceding fcTL"),
+        }
+    }
+}
+
+impl std::error::Error for ApngError {}
@@ -0,0 +1,154 @@
+//
+// author: xigang zhao
+//
+
+// Packs a directory tree into a tar archive so that it can travel as one
+// payload, and unpacks it again. Entry paths are relative to the packed
+// directory and keep their permissions and modification times.
+//
+// Only regular files and directories are packed or unpacked: a link could
+// point anywhere once extracted. Every entry is checked before anything is
+// written, so an archive with one bad path extracts nothing at all.
+
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use crate::archive_error::ArchiveError;
+use crate::Result;
+
+pub const MIME_TYPE: &str = "application/x-tar";
+
+// The set-user-ID, set-group-ID and sticky bits are never restored.
+const MODE_MASK: u32 = 0o7000;
+
+pub fn pack_dir(dir: &Path) -> Result<Vec<u8>> {
+    if !dir.is_dir() {
+        return Err(Box::from(ArchiveError::NotADirectory(dir.display().to_string())));
+    }
+    let mut builder = tar::Builder::new(Vec::new());
+    append_dir(&mut builder, dir, Path::new(""))?;
+    return Ok(builder.into_inner()?);
+}
+
+fn append_dir(builder: &mut tar::Builder<Vec<u8>>, dir: &Path, name: &Path) -> Result<()> {
+    let mut entries: Vec<fs::DirEntry> = fs::read_dir(dir)?.collect::<std::io::Result<_>>()?;
+    // the same tree always gives the same archive
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        let entry_name = name.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            builder.append_dir(&entry_name, &path)?;
+            append_dir(builder, &path, &entry_name)?;
+        } else if file_type.is_file() {
+            builder.append_path_with_name(&path, &entry_name)?;
+        } else {
+            return Err(Box::from(ArchiveError::UnsupportedEntry(path.display().to_string())));
+        }
+    }
+    return Ok(());
+}
+
+// Lists the paths in an archive, failing on the first one that isn't safe
+// to extract.
+pub fn entries(data: &[u8]) -> Result<Vec<PathBuf>> {
+    let mut archive = tar::Archive::new(data);
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let path = entry.path()?.to_path_buf();
+        let kind = entry.header().entry_type();
+        if !kind.is_file() && !kind.is_dir() {
+            return Err(Box::from(ArchiveError::UnsupportedEntry(path.display().to_string())));
+        }
+        if !is_safe(&path) {
+            return Err(Box::from(ArchiveError::UnsafePath(path.display().to_string())));
+        }
+        paths.push(path);
+    }
+    return Ok(paths);
+}
+
+// A path is safe if it only goes down: no root, no drive prefix and no "..".
+fn is_safe(path: &Path) -> bool {
+    return path.components().next().is_some()
+           && path.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+}
+
+pub fn unpack(data: &[u8], dest: &Path) -> Result<Vec<PathBuf>> {
+    let paths = entries(data)?;
+    fs::create_dir_all(dest)?;
+    let mut archive = tar::Archive::new(data);
+    archive.set_preserve_permissions(true);
+    archive.set_preserve_mtime(true);
+    archive.set_mask(MODE_MASK);
+    archive.unpack(dest)?;
+    return Ok(paths);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("deepng-archive-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn archive_with(path: &str) -> Vec<u8> {
+        let mut header = tar::Header::new_gnu();
+        header.as_gnu_mut().unwrap().name[..path.len()].copy_from_slice(path.as_bytes());
+        header.set_size(4);
+        header.set_mode(0o644);
+        header.set_cksum();
+        let mut builder = tar::Builder::new(Vec::new());
+        builder.append(&header, &b"evil"[..]).unwrap();
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let src = temp_dir("src");
+        fs::create_dir_all(src.join("conf/sub")).unwrap();
+        fs::write(src.join("conf/a.toml"), b"a = 1").unwrap();
+        fs::write(src.join("conf/sub/run.sh"), b"#!/bin/sh").unwrap();
+        fs::set_permissions(src.join("conf/sub/run.sh"), fs::Permissions::from_mode(0o750)).unwrap();
+        let data = pack_dir(&src).unwrap();
+        assert_eq!(entries(&data).unwrap(), vec![
+            PathBuf::from("conf/"), PathBuf::from("conf/a.toml"),
+            PathBuf::from("conf/sub/"), PathBuf::from("conf/sub/run.sh"),
+        ]);
+
+        let dest = temp_dir("dest");
+        unpack(&data, &dest).unwrap();
+        assert_eq!(fs::read(dest.join("conf/a.toml")).unwrap(), b"a = 1");
+        let meta = fs::metadata(dest.join("conf/sub/run.sh")).unwrap();
+        assert_eq!(meta.permissions().mode() & 0o777, 0o750);
+        let before = fs::metadata(src.join("conf/a.toml")).unwrap().modified().unwrap();
+        let after = fs::metadata(dest.join("conf/a.toml")).unwrap().modified().unwrap();
+        assert_eq!(before.duration_since(std::time::UNIX_EPOCH).unwrap().as_secs(),
+                   after.duration_since(std::time::UNIX_EPOCH).unwrap().as_secs());
+        fs::remove_dir_all(&src).unwrap();
+        fs::remove_dir_all(&dest).unwrap();
+    }
+
+    #[test]
+    fn test_unsafe_paths() {
+        let dest = temp_dir("unsafe");
+        for path in ["../evil", "a/../../evil", "/tmp/evil"] {
+            let data = archive_with(path);
+            assert!(unpack(&data, &dest.join("out")).is_err(), "{}", path);
+        }
+        assert!(!dest.join("out").exists());
+        assert!(!dest.parent().unwrap().join("evil").exists());
+        fs::remove_dir_all(&dest).unwrap();
+    }
+
+    #[test]
+    fn test_not_a_directory() {
+        assert!(pack_dir(Path::new("/nonexistent/deepng")).is_err());
+    }
+}
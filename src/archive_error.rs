@@ -0,0 +1,24 @@
+//
+// author: xigang zhao
+//
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum ArchiveError {
+    NotADirectory(String),
+    UnsupportedEntry(String),
+    UnsafePath(String),
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArchiveError::NotADirectory(path)    => write!(f, "{} is not a directory", path),
+            ArchiveError::UnsupportedEntry(path) => write!(f, "{} is not a regular file or directory", path),
+            ArchiveError::UnsafePath(path)       => write!(f, "Refusing to extract {} outside the target directory", path),
+        }
+    }
+}
+
+impl std::error::Error for ArchiveError {}
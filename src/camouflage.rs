@@ -0,0 +1,94 @@
+//
+// author: xigang zhao
+//
+
+// Derives a private, safe-to-copy chunk type from a passphrase, so that the
+// decoder only needs the key and different payloads don't share a type.
+
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+use crate::chunk_type::ChunkType;
+
+// Plausible lowercase names. The third letter is upper-cased when building
+// the chunk type, which keeps it ancillary, private and safe to copy. With
+// 256 names, two keys pick the same one about once in 256.
+const NAMES: [&str; 256] = [
+    "meta", "info", "data", "tags", "note", "memo", "prof", "colr",
+    "hist", "layr", "mask", "view", "zoom", "crop", "rota", "flip",
+    "exif", "xmpd", "iccp", "gain", "tone", "curv", "lvls", "hues",
+    "satr", "blur", "shrp", "nois", "grad", "fill", "strk", "path",
+    "font", "glyp", "text", "caps", "anno", "mark", "pins", "grid",
+    "snap", "rulr", "guid", "cach", "thmb", "prev", "time", "date",
+    "auth", "lice", "copy", "orig", "vers", "edit", "undo", "redo",
+    "sess", "work", "proj", "scen", "cams", "lens", "expo", "bias",
+    "wbal", "tint", "temp", "vign", "dhaz", "clar", "vibr", "gamm",
+    "blck", "whit", "shdw", "midt", "cntr", "brgt", "expc", "flsh",
+    "aper", "shut", "fcal", "focl", "dist", "zoml", "geop", "gpsi",
+    "alti", "lati", "long", "head", "tilt", "roll", "orie", "dpth",
+    "disp", "occl", "alph", "prem", "comp", "blnd", "opac", "ovly",
+    "scrn", "mult", "diff", "excl", "dodg", "burn", "smdg", "clne",
+    "heal", "patc", "stmp", "brsh", "pncl", "ersr", "bckt", "slct",
+    "lass", "wand", "pths", "shps", "rect", "ellp", "poly", "star",
+    "line", "arrw", "bzir", "node", "anch", "hndl", "grps", "objs",
+    "symb", "swch", "pltt", "swat", "clrs", "spot", "proc", "ints",
+    "lyrs", "fldr", "artb", "pags", "sprd", "mstr", "pref", "sett",
+    "conf", "opts", "envr", "plug", "extn", "modl", "scrp", "macr",
+    "actn", "btch", "rndr", "prvw", "bake", "lite", "shad", "refl",
+    "refr", "glos", "spec", "nrml", "bump", "metl", "emis", "uvmp",
+    "wrap", "tile", "ofst", "scal", "rotn", "skew", "pers", "warp",
+    "mesh", "liqf", "puck", "blot", "twrl", "zigz", "ripl", "wave",
+    "noiz", "dthr", "post", "thrs", "quan", "idxd", "pals", "lutb",
+    "cube", "grde", "fade", "crss", "wipe", "slde", "push", "echo",
+    "lyrc", "subt", "capt", "desc", "titl", "kwds", "ctgy", "rate",
+    "flag", "labl", "stat", "wflw", "ownr", "rght", "usge", "cred",
+    "srce", "prvd", "scan", "dpis", "unit", "size", "bnds", "clip",
+    "pane", "cell", "rows", "cols", "fram", "anim", "loop", "dlay",
+    "sync", "seqn", "trak", "mtrx", "kern", "lead", "algn", "just",
+    "indt", "bulb", "hash", "sums", "sign", "keys", "salt", "nonc",
+];
+
+pub fn chunk_type_from_key(key: &str) -> ChunkType {
+    let digest = Zeroizing::new(<[u8; 32]>::from(Sha256::digest(key.as_bytes())));
+    let index = u16::from_be_bytes([digest[0], digest[1]]) as usize % NAMES.len();
+    let mut code: [u8; 4] = NAMES[index].as_bytes().try_into().unwrap();
+    code[2] = code[2].to_ascii_uppercase();
+    return ChunkType::try_from(code).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names_are_lowercase() {
+        assert!(NAMES.iter().all(|n| n.len() == 4 && n.bytes().all(|b| b.is_ascii_lowercase())));
+    }
+
+    #[test]
+    fn test_names_are_distinct() {
+        let names: std::collections::HashSet<&str> = NAMES.iter().copied().collect();
+        assert_eq!(names.len(), NAMES.len());
+    }
+
+    #[test]
+    fn test_key_type_is_valid() {
+        for key in ["", "hunter2", "correct horse battery staple"] {
+            let ck_type = chunk_type_from_key(key);
+            assert!(ck_type.is_valid_type());
+            assert!(!ck_type.is_critical() && !ck_type.is_public() && ck_type.is_safe_to_copy());
+        }
+    }
+
+    #[test]
+    fn test_key_type_is_deterministic() {
+        assert_eq!(chunk_type_from_key("passphrase"), chunk_type_from_key("passphrase"));
+    }
+
+    #[test]
+    fn test_keys_spread_over_pool() {
+        let types: std::collections::HashSet<String> = (0..1000)
+            .map(|i| chunk_type_from_key(&format!("key{}", i)).to_string())
+            .collect();
+        assert!(types.len() > 200);
+    }
+}
@@ -0,0 +1,258 @@
+//
+// author: xigang zhao
+//
+
+// Works out how many bytes each embedding method can carry in an image once
+// the payload container is added, from the IHDR geometry, and warns about
+// methods that deepng's own scan and analyze would give away. The pixel
+// methods are only there for comparison; encode doesn't offer them.
+
+use std::convert::TryFrom;
+use std::fmt;
+use crate::apng;
+use crate::container::{self, PackOptions};
+use crate::cover_text;
+use crate::crypto;
+use crate::fctl_stego;
+use crate::ihdr::ImageHeader;
+use crate::pixels::Pixels;
+use crate::png::Png;
+use crate::png_error::PngError;
+use crate::scan;
+use crate::Result;
+
+// The PNG spec limits chunk lengths to 2^31 - 1.
+pub const MAX_CHUNK_LENGTH: usize = (1 << 31) - 1;
+// a 79-byte keyword and the iTXt fields around it
+const TEXT_FIELDS: usize = 84;
+// RS analysis picks up LSB replacement in about this share of the samples.
+const LSB_DETECTABLE_RATE: f64 = 0.05;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Limit {
+    Bytes(usize),
+    Unlimited,
+    Unavailable(String),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Limit::Bytes(bytes)         => write!(f, "{} bytes", bytes),
+            Limit::Unlimited            => write!(f, "no limit"),
+            Limit::Unavailable(reason)  => write!(f, "- ({})", reason),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MethodCapacity {
+    method   : &'static str,
+    limit    : Limit,
+    warning  : Option<String>, // why using this method would stand out
+    encodable: bool,           // whether encode --method offers it
+}
+
+impl fmt::Display for MethodCapacity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:<8} {:>18}", self.method, self.limit.to_string())?;
+        if !self.encodable {
+            write!(f, "  (not supported by encode)")?;
+        }
+        if let Some(warning) = &self.warning {
+            write!(f, "  ! {}", warning)?;
+        }
+        Ok(())
+    }
+}
+
+impl MethodCapacity {
+    fn new(method: &'static str, limit: Limit, warning: Option<String>) -> MethodCapacity {
+        return MethodCapacity { method, limit, warning, encodable: true };
+    }
+
+    // A method that is only reported, for comparison: the pixel methods.
+    fn not_encodable(self) -> MethodCapacity {
+        return MethodCapacity { encodable: false, ..self };
+    }
+
+    pub fn method(&self) -> &str {
+        return self.method;
+    }
+}
+
+// Size of what an embedding method stores for `size` bytes of data. An
+// encrypted payload is packed, sealed and packed again, with the error
+// correction on the outside.
+pub fn payload_size(size: usize, options: &PackOptions) -> usize {
+    if !options.encrypted {
+        return container::packed_size(size, options);
+    }
+    let inner = PackOptions { fec: None, encrypted: false, ..options.clone() };
+    let outer = PackOptions { fec: options.fec, encrypted: true, ..Default::default() };
+    return container::packed_size(crypto::sealed_size(container::packed_size(size, &inner)), &outer);
+}
+
+// The largest payload that fits in `raw` bytes. A container holds at most
+// 2^32 - 1 bytes, however much room there is.
+pub fn fit(raw: usize, options: &PackOptions) -> usize {
+    if payload_size(0, options) > raw {
+        return 0;
+    }
+    let (mut low, mut high) = (0, raw.min(u32::MAX as usize));
+    while low < high {
+        let mid = low + (high - low).div_ceil(2);
+        if payload_size(mid, options) <= raw {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    return low;
+}
+
+pub fn capacity(png: &Png, options: &PackOptions) -> Result<Vec<MethodCapacity>> {
+    let header = ImageHeader::try_from(png.chunk_by_type("IHDR").ok_or(PngError::InvalidImageHeader)?)?;
+    let mut methods: Vec<MethodCapacity> = Vec::new();
+
+    methods.push(MethodCapacity::new("chunk", Limit::Bytes(fit(MAX_CHUNK_LENGTH, options)),
+                                     Some("scan reports private chunks".to_string())));
+    let word = cover_text::max_text_len(1);
+    let quiet = fit(scan::LARGE_TEXT / word, options);
+    methods.push(MethodCapacity::new("text", Limit::Bytes(fit((MAX_CHUNK_LENGTH - TEXT_FIELDS) / word, options)),
+                                     Some(format!("scan reports text over {} bytes, about {} bytes of payload",
+                                                  scan::LARGE_TEXT, quiet))));
+    methods.push(MethodCapacity::new("trailer", Limit::Unlimited,
+                                     Some("scan reports data after IEND".to_string())));
+    let fctl = match apng::is_animated(png) {
+        true => Limit::Bytes(fit(fctl_stego::capacity(png)?.min(u16::MAX as usize), options)),
+        false => Limit::Unavailable("not an animated PNG".to_string()),
+    };
+    methods.push(MethodCapacity::new("fctl", fctl, None));
+
+    let pixels = (header.width() as usize).saturating_mul(header.height() as usize);
+    let samples = pixels.saturating_mul(header.channels());
+    let lsb_unavailable = match (header.color_type(), header.bit_depth()) {
+        (3, _) => Some("palette image"),
+        (_, depth) if depth < 8 => Some("fewer than 8 bits per sample"),
+        _ => None,
+    };
+    for bits in 1..=3 {
+        let method = ["lsb-1", "lsb-2", "lsb-3"][bits - 1];
+        if let Some(reason) = lsb_unavailable {
+            methods.push(MethodCapacity::new(method, Limit::Unavailable(reason.to_string()), None).not_encodable());
+            continue;
+        }
+        let raw = samples.saturating_mul(bits) / 8;
+        let warning = match bits {
+            1 => {
+                let detectable = fit((raw as f64 * LSB_DETECTABLE_RATE) as usize, options);
+                format!("RS analysis detects more than about {} bytes", detectable)
+            }
+            _ => format!("RS and sample pair analysis detect changes to {} bit planes", bits),
+        };
+        methods.push(MethodCapacity::new(method, Limit::Bytes(fit(raw, options)), Some(warning)).not_encodable());
+    }
+
+    if lsb_unavailable.is_some() || !header.has_alpha() {
+        let reason = lsb_unavailable.unwrap_or("no alpha channel");
+        methods.push(MethodCapacity::new("alpha", Limit::Unavailable(reason.to_string()), None).not_encodable());
+    } else {
+        let raw = pixels / 8;
+        let warning = match uniform_alpha(png) {
+            true => Some("the alpha channel is uniform, so any change stands out".to_string()),
+            false => None,
+        };
+        methods.push(MethodCapacity::new("alpha", Limit::Bytes(fit(raw, options)), warning).not_encodable());
+    }
+    return Ok(methods);
+}
+
+// Whether every pixel has the same alpha, as in most images that have an
+// alpha channel they don't use. Undecodable images count as not uniform.
+fn uniform_alpha(png: &Png) -> bool {
+    let pixels = match Pixels::decode(png) {
+        Ok(pixels) => pixels,
+        Err(_) => return false,
+    };
+    let channels = pixels.header().channels();
+    let samples = pixels.samples();
+    let mut alpha = samples.iter().skip(channels - 1).step_by(channels);
+    let first = alpha.next();
+    return alpha.all(|a| Some(a) == first);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use crate::pixels::tests::testing_png;
+
+    fn limit(methods: &[MethodCapacity], method: &str) -> Limit {
+        methods.iter().find(|m| m.method() == method).unwrap().limit.clone()
+    }
+
+    #[test]
+    fn test_fit() {
+        let options = PackOptions { fec: Some(16), ..Default::default() };
+        for raw in [0, 17, 18, 100, 1000, 100_000] {
+            let n = fit(raw, &options);
+            assert!(raw < container::packed_size(0, &options) || container::packed_size(n, &options) <= raw);
+            assert!(container::packed_size(n + 1, &options) > raw);
+        }
+        assert_eq!(fit(100, &PackOptions::default()), 82);
+    }
+
+    #[test]
+    fn test_encrypted() {
+        let options = PackOptions { encrypted: true, ..Default::default() };
+        let sealed = crypto::seal(&container::pack(b"hello", &PackOptions::default()).unwrap(), "pw", None,
+                                  &crypto::KdfParams { m_cost: 64, t_cost: 1, p_cost: 1 }).unwrap();
+        let packed = container::pack(&sealed, &PackOptions { encrypted: true, ..Default::default() }).unwrap();
+        assert_eq!(payload_size(5, &options), packed.len());
+        assert_eq!(fit(packed.len(), &options), 5);
+    }
+
+    #[test]
+    fn test_rgb() {
+        let methods = capacity(&testing_png(32, 32, 2, 0), &PackOptions::default()).unwrap();
+        assert_eq!(limit(&methods, "lsb-1"), Limit::Bytes(32 * 32 * 3 / 8 - 18));
+        assert_eq!(limit(&methods, "lsb-3"), Limit::Bytes(32 * 32 * 9 / 8 - 18));
+        assert_eq!(limit(&methods, "trailer"), Limit::Unlimited);
+        assert!(matches!(limit(&methods, "fctl"), Limit::Unavailable(_)));
+        assert!(matches!(limit(&methods, "alpha"), Limit::Unavailable(_)));
+        assert!(methods.iter().find(|m| m.method() == "chunk").unwrap().warning.is_some());
+        for m in &methods {
+            let encodable = ["chunk", "text", "trailer", "fctl"].contains(&m.method());
+            assert_eq!(m.to_string().contains("not supported by encode"), !encodable);
+        }
+    }
+
+    #[test]
+    fn test_alpha() {
+        let methods = capacity(&testing_png(32, 32, 6, 0), &PackOptions::default()).unwrap();
+        let alpha = methods.iter().find(|m| m.method() == "alpha").unwrap();
+        assert_eq!(alpha.limit, Limit::Bytes(32 * 32 / 8 - 18));
+        assert!(alpha.warning.is_none());
+    }
+
+    #[test]
+    fn test_huge_image() {
+        let mut ihdr = 0x7fff_ffffu32.to_be_bytes().repeat(2);
+        ihdr.extend_from_slice(&[16, 6, 0, 0, 0]);
+        let png = Png::from_chunks(vec![Chunk::new(ChunkType::from_str("IHDR").unwrap(), ihdr)]);
+        let methods = capacity(&png, &PackOptions { fec: Some(8), ..Default::default() }).unwrap();
+        for method in ["lsb-1", "lsb-3", "alpha"] {
+            assert!(matches!(limit(&methods, method), Limit::Bytes(n) if n == u32::MAX as usize));
+        }
+    }
+
+    #[test]
+    fn test_palette() {
+        let methods = capacity(&testing_png(8, 8, 3, 0), &PackOptions::default()).unwrap();
+        assert!(matches!(limit(&methods, "lsb-1"), Limit::Unavailable(_)));
+        assert!(matches!(limit(&methods, "chunk"), Limit::Bytes(_)));
+    }
+}
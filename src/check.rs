@@ -0,0 +1,147 @@
+//
+// author: xigang zhao
+//
+
+// Structural checks on a parsed PNG. CRCs are already verified when parsing.
+
+use std::convert::TryFrom;
+use std::fmt;
+use crate::apng;
+use crate::ihdr::ImageHeader;
+use crate::png::Png;
+use crate::policy::Policy;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Finding {
+    severity: Severity,
+    message : String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "warning: {}", self.message),
+            Severity::Error   => write!(f, "error: {}", self.message),
+        }
+    }
+}
+
+impl Finding {
+    pub fn new(severity: Severity, message: String) -> Finding {
+        return Finding { severity, message };
+    }
+}
+
+pub fn check(png: &Png) -> Vec<Finding> {
+    let mut findings: Vec<Finding> = Vec::new();
+    let chunks = png.chunks();
+    match chunks.first() {
+        Some(ck) if &ck.chunk_type().bytes() == b"IHDR" => {
+            if let Err(e) = ImageHeader::try_from(ck) {
+                findings.push(Finding::new(Severity::Error, e.to_string()));
+            }
+        }
+        _ => findings.push(Finding::new(Severity::Error, "first chunk is not IHDR".to_string())),
+    }
+    if png.chunk_by_type("IDAT").is_none() {
+        findings.push(Finding::new(Severity::Error, "no IDAT chunk".to_string()));
+    }
+    match chunks.iter().position(|ck| &ck.chunk_type().bytes() == b"IEND") {
+        None => findings.push(Finding::new(Severity::Error, "no IEND chunk".to_string())),
+        Some(i) if i + 1 < chunks.len() => {
+            let after = chunks.len() - i - 1;
+            findings.push(Finding::new(Severity::Warning, format!("{} chunk(s) after IEND", after)));
+        }
+        _ => {}
+    }
+    if apng::is_animated(png) && let Err(e) = apng::validate(png) {
+        findings.push(Finding::new(Severity::Error, format!("animation: {}", e)));
+    }
+    if !png.trailer().is_empty() {
+        let message = format!("{} bytes of trailing data after IEND", png.trailer().len());
+        findings.push(Finding::new(Severity::Warning, message));
+    }
+    return findings;
+}
+
+// Reports every chunk the policy denies.
+pub fn check_policy(png: &Png, policy: &Policy) -> Vec<Finding> {
+    let mut findings: Vec<Finding> = Vec::new();
+    for (i, ck) in png.chunks().iter().enumerate() {
+        if policy.allows(ck.chunk_type()) {
+            continue;
+        }
+        let reason = match policy.rule_for(ck.chunk_type()) {
+            Some(rule) => rule.to_string(),
+            None => "default deny".to_string(),
+        };
+        let message = format!("chunk #{} {} is denied by the policy ({})", i, ck.chunk_type(), reason);
+        findings.push(Finding::new(Severity::Error, message));
+    }
+    return findings;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+
+    fn chunk(chunk_type: &str, data: Vec<u8>) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+    }
+
+    fn testing_png() -> Png {
+        let mut ihdr = 4u32.to_be_bytes().to_vec();
+        ihdr.extend_from_slice(&4u32.to_be_bytes());
+        ihdr.extend_from_slice(&[8, 2, 0, 0, 0]);
+        Png::from_chunks(vec![chunk("IHDR", ihdr), chunk("IDAT", vec![0]), chunk("IEND", vec![])])
+    }
+
+    #[test]
+    fn test_clean_file() {
+        assert!(check(&testing_png()).is_empty());
+    }
+
+    #[test]
+    fn test_trailing_data() {
+        let mut png = testing_png();
+        png.set_trailer(b"extra".to_vec());
+        let findings = check(&png);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].severity, Severity::Warning);
+        assert_eq!(findings[0].message, "5 bytes of trailing data after IEND");
+    }
+
+    #[test]
+    fn test_chunk_after_iend() {
+        let mut png = testing_png();
+        png.append_chunk(chunk("ruSt", vec![1]));
+        assert_eq!(check(&png)[0].message, "1 chunk(s) after IEND");
+    }
+
+    #[test]
+    fn test_policy() {
+        let mut png = testing_png();
+        png.insert_chunk(1, chunk("ruSt", vec![1]));
+        png.insert_chunk(1, chunk("tEXt", b"a\0b".to_vec()));
+        let policy = Policy::from_str("deny private\nallow critical\ndefault deny").unwrap();
+        let findings = check_policy(&png, &policy);
+        assert_eq!(findings.len(), 2);
+        assert_eq!(findings[0].message, "chunk #1 tEXt is denied by the policy (default deny)");
+        assert_eq!(findings[1].message, "chunk #2 ruSt is denied by the policy (line 1: deny private)");
+    }
+
+    #[test]
+    fn test_missing_chunks() {
+        let png = Png::from_chunks(vec![chunk("ruSt", vec![1])]);
+        assert_eq!(check(&png).len(), 3);
+    }
+}
@@ -57,7 +57,7 @@ impl TryFrom<&[u8]> for Chunk {
 impl fmt::Display for Chunk {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "length: {}", self.length())?;
-        writeln!(f, "chunk type: {}", self.chunk_type().to_string())?;
+        writeln!(f, "chunk type: {}", self.chunk_type())?;
         writeln!(f, "data: ")?;
         for (i, byte) in self.data().iter().enumerate() {
             if (i+1) % 16 == 0 || (i+1) == self.data().len() {
@@ -93,7 +93,7 @@ impl Chunk {
     }
 
     pub fn data(&self) -> &[u8] {
-        return &self.chunkdata.as_slice();
+        return self.chunkdata.as_slice();
     }
 
     pub fn crc(&self) -> u32 {
@@ -109,9 +109,9 @@ impl Chunk {
 
     pub fn as_bytes(&self) -> Vec<u8> {
         let mut res: Vec<u8> = self.length.to_be_bytes().to_vec();
-        res.extend_from_slice(&self.chunktype.bytes().to_vec());
+        res.extend_from_slice(&self.chunktype.bytes());
         res.extend_from_slice(&self.chunkdata);
-        res.extend_from_slice(&self.crc.to_be_bytes().to_vec());
+        res.extend_from_slice(&self.crc.to_be_bytes());
         return res;
     }
 }
@@ -7,7 +7,7 @@ use std::str::FromStr;
 use std::fmt;
 use crate::chunk_type_error::ChunkTypeError;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ChunkType {
     code : [u8; 4],
 }
@@ -46,7 +46,7 @@ impl TryFrom<[u8; 4]> for ChunkType {
     // type Error = &'static str;
     type Error = ChunkTypeError;
     fn try_from(value: [u8; 4]) -> Result<Self, Self::Error> {
-        let all_valid = value.iter().all(|&x| (x >= 65 && x <= 90) || (x >= 97 && x <= 122));
+        let all_valid = value.iter().all(|&x| x.is_ascii_alphabetic());
         if all_valid == true {
             Ok(ChunkType { code: value })
         } else {
@@ -0,0 +1,401 @@
+//
+// author: xigang zhao
+//
+
+// The payload container every embedding method carries. It lets decode
+// recognise a deepng payload and learn how it was built:
+//
+//     magic "dpNG" | version | flags | header length (2) | size (4) | CRC-32 (4)
+//     | name length (1) | name | MIME type length (1) | MIME type | body
+//
+// The version byte holds the major version in the high nibble and the minor
+// version in the low one. Minor versions only append header fields, which
+// older readers skip using the header length; a new major version can't be
+// read. Size and CRC-32 are those of the original data, and the body is that
+// data after the transformations the flags name: compression, then padding
+// (the real length (4), the data and zeros up to the size the padding scheme
+// picks for the whole container). The ENCRYPTED, FRAGMENTED and SHARE flags
+// transform nothing: they mark the data as another container sealed with a
+// password, as one fragment of a payload spread over several images, or as
+// one share of a secret split between them.
+//
+// Error correction covers the whole container, header included, so a damaged
+// magic number or flags byte doesn't hide the payload: the container is
+// Reed-Solomon encoded as it is. The FEC flag is never written in a header:
+// unpack sets it on the payload when it finds the container encoded.
+
+use crate::container_error::ContainerError;
+use crate::padding::Padding;
+use crate::{fec, zlib};
+use crate::Result;
+
+pub const MAGIC: &[u8; 4] = b"dpNG";
+pub const VERSION_MAJOR: u8 = 1;
+pub const VERSION_MINOR: u8 = 0;
+
+pub const COMPRESSED: u8 = 0x01;
+pub const ENCRYPTED : u8 = 0x02;
+pub const FRAGMENTED: u8 = 0x04;
+pub const FEC       : u8 = 0x08;
+pub const SHARE     : u8 = 0x10;
+pub const PADDED    : u8 = 0x20;
+const SUPPORTED: u8 = COMPRESSED | ENCRYPTED | FRAGMENTED | SHARE | PADDED;
+
+// magic, version, flags and header length
+const PREFIX_SIZE: usize = 8;
+// size, checksum and the two length bytes
+const MIN_HEADER_SIZE: usize = 10;
+// the real length at the start of a padded body
+const PADDED_LENGTH_SIZE: usize = 4;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PackOptions {
+    pub name     : Option<String>,
+    pub mime_type: Option<String>,
+    pub compress : bool,
+    pub fec      : Option<u8>, // parity bytes per block
+    pub padding  : Option<Padding>,
+    pub encrypted: bool,
+    pub fragment : bool,
+    pub share    : bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Payload {
+    version  : (u8, u8),
+    flags    : u8,
+    name     : Option<String>,
+    mime_type: Option<String>,
+    data     : Vec<u8>,
+    corrected: usize,
+}
+
+impl Payload {
+    pub fn version(&self) -> (u8, u8) {
+        return self.version;
+    }
+
+    pub fn flags(&self) -> u8 {
+        return self.flags;
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        return self.name.as_deref();
+    }
+
+    pub fn mime_type(&self) -> Option<&str> {
+        return self.mime_type.as_deref();
+    }
+
+    pub fn data(&self) -> &[u8] {
+        return &self.data;
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        return self.data;
+    }
+
+    // Bytes repaired by error correction.
+    pub fn corrected(&self) -> usize {
+        return self.corrected;
+    }
+}
+
+pub fn is_container(bytes: &[u8]) -> bool {
+    return bytes.starts_with(MAGIC) || decode_fec(bytes).is_some();
+}
+
+// The container inside error correction, and the number of bytes repaired.
+fn decode_fec(bytes: &[u8]) -> Option<(Vec<u8>, usize)> {
+    return fec::decode(bytes).ok().filter(|(container, _)| container.starts_with(MAGIC));
+}
+
+pub fn pack(data: &[u8], options: &PackOptions) -> Result<Vec<u8>> {
+    let name = options.name.as_deref().unwrap_or("").as_bytes();
+    let mime_type = options.mime_type.as_deref().unwrap_or("").as_bytes();
+    if name.len() > u8::MAX as usize {
+        return Err(Box::from(ContainerError::FieldTooLong("file name")));
+    }
+    if mime_type.len() > u8::MAX as usize {
+        return Err(Box::from(ContainerError::FieldTooLong("MIME type")));
+    }
+    if data.len() > u32::MAX as usize {
+        return Err(Box::from(ContainerError::FieldTooLong("payload")));
+    }
+    let header_size = MIN_HEADER_SIZE + name.len() + mime_type.len();
+    let mut flags = 0;
+    let mut body = data.to_vec();
+    if options.compress {
+        flags |= COMPRESSED;
+        body = zlib::deflate(&body);
+    }
+    if let Some(padding) = &options.padding {
+        flags |= PADDED;
+        let size = PREFIX_SIZE + header_size + PADDED_LENGTH_SIZE + body.len();
+        let mut padded = (body.len() as u32).to_be_bytes().to_vec();
+        padded.extend_from_slice(&body);
+        let target = padding.target(size).ok_or(ContainerError::PaddingTooLarge)?;
+        padded.resize(padded.len() + target - size, 0);
+        body = padded;
+    }
+    if options.encrypted {
+        flags |= ENCRYPTED;
+    }
+    if options.fragment {
+        flags |= FRAGMENTED;
+    }
+    if options.share {
+        flags |= SHARE;
+    }
+    let mut bytes = MAGIC.to_vec();
+    bytes.push(VERSION_MAJOR << 4 | VERSION_MINOR);
+    bytes.push(flags);
+    bytes.extend_from_slice(&(header_size as u16).to_be_bytes());
+    bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(&crc32fast::hash(data).to_be_bytes());
+    bytes.push(name.len() as u8);
+    bytes.extend_from_slice(name);
+    bytes.push(mime_type.len() as u8);
+    bytes.extend_from_slice(mime_type);
+    bytes.extend_from_slice(&body);
+    if let Some(parity) = options.fec {
+        bytes = fec::encode(&bytes, parity)?;
+    }
+    return Ok(bytes);
+}
+
+// Size of the container for `size` bytes of data, with as much padding as
+// the scheme can add. Compression is left out, since how much it saves
+// depends on the data.
+pub fn packed_size(size: usize, options: &PackOptions) -> usize {
+    let name = options.name.as_deref().map_or(0, str::len);
+    let mime_type = options.mime_type.as_deref().map_or(0, str::len);
+    let header_size = PREFIX_SIZE + MIN_HEADER_SIZE + name + mime_type;
+    let body = match &options.padding {
+        // too large to pack at all, so larger than any room there is
+        Some(padding) => match padding.max_target(header_size + PADDED_LENGTH_SIZE + size) {
+            Some(target) => target - header_size,
+            None => return usize::MAX,
+        },
+        None => size,
+    };
+    return match options.fec {
+        Some(parity) => fec::encoded_size(header_size + body, parity),
+        None => header_size + body,
+    };
+}
+
+pub fn unpack(bytes: &[u8]) -> Result<Payload> {
+    if !bytes.starts_with(MAGIC) && let Some((container, corrected)) = decode_fec(bytes) {
+        let mut payload = unpack(&container)?;
+        payload.flags |= FEC;
+        payload.corrected += corrected;
+        return Ok(payload);
+    }
+    if !bytes.starts_with(MAGIC) || bytes.len() < PREFIX_SIZE + MIN_HEADER_SIZE {
+        return Err(Box::from(ContainerError::InvalidHeader));
+    }
+    let version = (bytes[4] >> 4, bytes[4] & 0x0f);
+    if version.0 != VERSION_MAJOR {
+        return Err(Box::from(ContainerError::UnsupportedVersion(version.0, version.1)));
+    }
+    let flags = bytes[5];
+    if flags & !SUPPORTED != 0 {
+        return Err(Box::from(ContainerError::UnsupportedFlags(flags)));
+    }
+    let header_size = u16::from_be_bytes([bytes[6], bytes[7]]) as usize;
+    let header = bytes.get(PREFIX_SIZE..PREFIX_SIZE + header_size).ok_or(ContainerError::InvalidHeader)?;
+    let mut fields = Fields { data: header, offset: 0 };
+    let size = u32::from_be_bytes(fields.take(4)?.try_into().unwrap()) as usize;
+    let checksum = u32::from_be_bytes(fields.take(4)?.try_into().unwrap());
+    let name = fields.take_string()?;
+    let mime_type = fields.take_string()?;
+    // anything after the known fields was added by a later minor version
+
+    let mut data = bytes[PREFIX_SIZE + header_size..].to_vec();
+    if flags & PADDED != 0 {
+        let length = data.get(..PADDED_LENGTH_SIZE).ok_or(ContainerError::InvalidPadding)?;
+        let length = u32::from_be_bytes(length.try_into().unwrap()) as usize;
+        data = data.get(PADDED_LENGTH_SIZE..PADDED_LENGTH_SIZE + length).ok_or(ContainerError::InvalidPadding)?.to_vec();
+    }
+    if flags & COMPRESSED != 0 {
+        data = zlib::inflate(&data)?;
+    }
+    if data.len() != size {
+        return Err(Box::from(ContainerError::SizeMismatch(data.len(), size)));
+    }
+    if crc32fast::hash(&data) != checksum {
+        return Err(Box::from(ContainerError::ChecksumMismatch));
+    }
+    return Ok(Payload { version, flags, name, mime_type, data, corrected: 0 });
+}
+
+// Guesses a MIME type from a file extension.
+pub fn mime_type(file_name: &str) -> Option<&'static str> {
+    let extension = file_name.rsplit_once('.')?.1.to_ascii_lowercase();
+    let mime_type = match extension.as_str() {
+        "txt" | "md"    => "text/plain",
+        "html" | "htm"  => "text/html",
+        "csv"           => "text/csv",
+        "json"          => "application/json",
+        "xml"           => "application/xml",
+        "pdf"           => "application/pdf",
+        "zip"           => "application/zip",
+        "gz"            => "application/gzip",
+        "tar"           => "application/x-tar",
+        "png"           => "image/png",
+        "jpg" | "jpeg"  => "image/jpeg",
+        "gif"           => "image/gif",
+        "webp"          => "image/webp",
+        "svg"           => "image/svg+xml",
+        "mp3"           => "audio/mpeg",
+        "mp4"           => "video/mp4",
+        _               => return None,
+    };
+    return Some(mime_type);
+}
+
+struct Fields<'a> {
+    data  : &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Fields<'a> {
+    fn take(&mut self, n: usize) -> std::result::Result<&'a [u8], ContainerError> {
+        let field = self.data.get(self.offset..self.offset + n).ok_or(ContainerError::InvalidHeader)?;
+        self.offset += n;
+        return Ok(field);
+    }
+
+    // A length-prefixed UTF-8 string; empty means not set.
+    fn take_string(&mut self) -> std::result::Result<Option<String>, ContainerError> {
+        let length = self.take(1)?[0] as usize;
+        let bytes = self.take(length)?;
+        let s = std::str::from_utf8(bytes).map_err(|_| ContainerError::InvalidHeader)?;
+        return Ok(if s.is_empty() { None } else { Some(s.to_string()) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> PackOptions {
+        PackOptions {
+            name     : Some("notes.txt".to_string()),
+            mime_type: Some("text/plain".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let packed = pack(b"hello world", &options()).unwrap();
+        assert!(is_container(&packed));
+        assert_eq!(packed.len(), 8 + 10 + 9 + 10 + 11);
+        let payload = unpack(&packed).unwrap();
+        assert_eq!(payload.version(), (VERSION_MAJOR, VERSION_MINOR));
+        assert_eq!(payload.flags(), 0);
+        assert_eq!(payload.name(), Some("notes.txt"));
+        assert_eq!(payload.mime_type(), Some("text/plain"));
+        assert_eq!(payload.data(), b"hello world");
+    }
+
+    #[test]
+    fn test_compress_and_fec() {
+        let data = b"all work and no play ".repeat(20);
+        let mut options = PackOptions { compress: true, fec: Some(8), ..Default::default() };
+        let mut packed = pack(&data, &options).unwrap();
+        packed[60] ^= 0xff;
+        let payload = unpack(&packed).unwrap();
+        assert_eq!(payload.flags(), COMPRESSED | FEC);
+        assert_eq!(payload.corrected(), 1);
+        assert_eq!(payload.data(), data.as_slice());
+        options.fec = None;
+        assert!(pack(&data, &options).unwrap().len() < data.len());
+    }
+
+    #[test]
+    fn test_fec_header() {
+        let options = PackOptions { fec: Some(4), ..options() };
+        let packed = pack(b"hello world", &options).unwrap();
+        assert!(is_container(&packed));
+        // any one flipped bit, in the magic number, flags or anywhere else
+        for i in 0..packed.len() * 8 {
+            let mut damaged = packed.clone();
+            damaged[i / 8] ^= 1 << (i % 8);
+            assert!(is_container(&damaged));
+            let payload = unpack(&damaged).unwrap();
+            assert_eq!(payload.flags(), FEC);
+            assert_eq!(payload.name(), Some("notes.txt"));
+            assert_eq!(payload.data(), b"hello world");
+        }
+    }
+
+    #[test]
+    fn test_packed_size() {
+        for fec in [None, Some(2), Some(32)] {
+            let options = PackOptions { fec, ..options() };
+            for size in [0, 1, 253, 1000] {
+                assert_eq!(packed_size(size, &options), pack(&vec![7; size], &options).unwrap().len());
+            }
+        }
+    }
+
+    #[test]
+    fn test_padding() {
+        let mut padded = PackOptions { padding: Some(Padding::PowerOfTwo), compress: true, ..options() };
+        for size in [0, 5, 100, 1000] {
+            let packed = pack(&vec![b'x'; size], &padded).unwrap();
+            assert!(packed.len().is_power_of_two());
+            assert_eq!(unpack(&packed).unwrap().data(), vec![b'x'; size].as_slice());
+        }
+        padded.fec = Some(4);
+        padded.compress = false;
+        assert_eq!(packed_size(100, &padded), pack(&[0; 100], &padded).unwrap().len());
+        let packed = pack(b"hi", &PackOptions { padding: Some(Padding::Buckets(vec![64])), ..options() }).unwrap();
+        assert_eq!(packed.len(), 64);
+        assert_eq!(unpack(&packed).unwrap().flags(), PADDED);
+    }
+
+    #[test]
+    fn test_checksum() {
+        let mut packed = pack(b"hello world", &PackOptions::default()).unwrap();
+        let last = packed.len() - 1;
+        packed[last] ^= 1;
+        assert!(matches!(unpack(&packed).unwrap_err().downcast_ref::<ContainerError>(),
+                         Some(ContainerError::ChecksumMismatch)));
+    }
+
+    #[test]
+    fn test_newer_minor_version() {
+        // a 1.1 header with two extra bytes
+        let mut packed = pack(b"hi", &options()).unwrap();
+        packed[4] = VERSION_MAJOR << 4 | 1;
+        let header_size = u16::from_be_bytes([packed[6], packed[7]]) + 2;
+        packed[6..8].copy_from_slice(&header_size.to_be_bytes());
+        let body_start = packed.len() - 2;
+        packed.splice(body_start..body_start, [0xab, 0xcd]);
+        let payload = unpack(&packed).unwrap();
+        assert_eq!(payload.version(), (1, 1));
+        assert_eq!(payload.data(), b"hi");
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let mut packed = pack(b"hi", &options()).unwrap();
+        packed[4] = 2 << 4;
+        assert!(matches!(unpack(&packed).unwrap_err().downcast_ref::<ContainerError>(),
+                         Some(ContainerError::UnsupportedVersion(2, 0))));
+        packed[4] = VERSION_MAJOR << 4;
+        packed[5] = 0x80;
+        assert!(unpack(&packed).is_err());
+        assert!(unpack(b"plain message").is_err());
+    }
+
+    #[test]
+    fn test_mime_type() {
+        assert_eq!(mime_type("Report.PDF"), Some("application/pdf"));
+        assert_eq!(mime_type("README"), None);
+    }
+}
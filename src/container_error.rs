@@ -0,0 +1,36 @@
+//
+// author: xigang zhao
+//
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum ContainerError {
+    InvalidHeader,
+    UnsupportedVersion(u8, u8),
+    UnsupportedFlags(u8),
+    FieldTooLong(&'static str),
+    SizeMismatch(usize, usize),
+    ChecksumMismatch,
+    InvalidPadding,
+    PaddingTooLarge,
+}
+
+impl fmt::Display for ContainerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ContainerError::InvalidHeader               => write!(f, "Truncated or malformed payload header"),
+            ContainerError::UnsupportedVersion(maj, min) =>
+                write!(f, "Payload format {}.{} needs a newer version of deepng", maj, min),
+            ContainerError::UnsupportedFlags(flags)     => write!(f, "Payload uses unsupported features (flags {:#04x})", flags),
+            ContainerError::FieldTooLong(field)         => write!(f, "The {} is longer than 255 bytes", field),
+            ContainerError::SizeMismatch(found, expected) =>
+                write!(f, "Payload is {} bytes, the header says {}", found, expected),
+            ContainerError::ChecksumMismatch            => write!(f, "Payload checksum does not match"),
+            ContainerError::InvalidPadding              => write!(f, "Payload padding is malformed"),
+            ContainerError::PaddingTooLarge             => write!(f, "The padded payload is too large"),
+        }
+    }
+}
+
+impl std::error::Error for ContainerError {}
@@ -0,0 +1,107 @@
+//
+// author: xigang zhao
+//
+
+// Digests of what a PNG shows rather than how it's stored, for finding
+// duplicates. The chunk digest ignores ancillary chunks and how the image
+// data is split into IDAT chunks; the pixel digest also ignores filtering,
+// compression and interlacing.
+
+use sha2::{Digest, Sha256};
+use crate::pixels::{self, Pixels};
+use crate::png::Png;
+use crate::png_error::PngError;
+use crate::Result;
+
+// SHA-256 over IHDR, PLTE and the IDAT data joined into one chunk, each as
+// type, length and data.
+pub fn chunk_digest(png: &Png) -> Result<[u8; 32]> {
+    let ihdr = png.chunk_by_type("IHDR").ok_or(PngError::InvalidImageHeader)?;
+    let mut hasher = Sha256::new();
+    hasher.update(b"IHDR");
+    hasher.update(ihdr.length().to_be_bytes());
+    hasher.update(ihdr.data());
+    if let Some(plte) = png.chunk_by_type("PLTE") {
+        hasher.update(b"PLTE");
+        hasher.update(plte.length().to_be_bytes());
+        hasher.update(plte.data());
+    }
+    let idat = pixels::idat_data(png);
+    hasher.update(b"IDAT");
+    hasher.update((idat.len() as u32).to_be_bytes());
+    hasher.update(&idat);
+    return Ok(hasher.finalize().into());
+}
+
+// SHA-256 over the image size, bit depth and color type, the palette of
+// indexed images and every sample, one byte each or two big-endian bytes
+// at 16 bits.
+pub fn pixel_digest(png: &Png) -> Result<[u8; 32]> {
+    let pixels = Pixels::decode(png)?;
+    let header = pixels.header();
+    let mut hasher = Sha256::new();
+    hasher.update(header.width().to_be_bytes());
+    hasher.update(header.height().to_be_bytes());
+    hasher.update([header.bit_depth(), header.color_type()]);
+    if header.color_type() == 3 {
+        hasher.update(png.chunk_by_type("PLTE").ok_or(PngError::UnsupportedImage)?.data());
+    }
+    let samples = pixels.samples();
+    let bytes: Vec<u8> = match header.bit_depth() {
+        16 => samples.iter().flat_map(|s| s.to_be_bytes()).collect(),
+        _  => samples.iter().map(|&s| s as u8).collect(),
+    };
+    hasher.update(&bytes);
+    return Ok(hasher.finalize().into());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use crate::pixels::tests::testing_png;
+
+    fn chunk(chunk_type: &str, data: Vec<u8>) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+    }
+
+    #[test]
+    fn test_ignores_ancillary_chunks() {
+        let png = testing_png(16, 16, 2, 0);
+        let mut other = testing_png(16, 16, 2, 0);
+        other.insert_chunk(1, chunk("tEXt", b"Comment\0hi".to_vec()));
+        other.set_trailer(b"extra".to_vec());
+        assert_eq!(chunk_digest(&png).unwrap(), chunk_digest(&other).unwrap());
+        assert_eq!(pixel_digest(&png).unwrap(), pixel_digest(&other).unwrap());
+    }
+
+    #[test]
+    fn test_split_idat() {
+        let png = testing_png(16, 16, 2, 0);
+        let data = png.chunks()[1].data().to_vec();
+        let mut split = testing_png(16, 16, 2, 0);
+        split.set_chunk(1, chunk("IDAT", data[..10].to_vec()));
+        split.insert_chunk(2, chunk("IDAT", data[10..].to_vec()));
+        assert_eq!(chunk_digest(&png).unwrap(), chunk_digest(&split).unwrap());
+    }
+
+    #[test]
+    fn test_recompressed() {
+        let png = testing_png(16, 16, 2, 0);
+        let filtered = testing_png(16, 16, 2, 1);
+        assert_ne!(chunk_digest(&png).unwrap(), chunk_digest(&filtered).unwrap());
+        assert_eq!(pixel_digest(&png).unwrap(), pixel_digest(&filtered).unwrap());
+    }
+
+    #[test]
+    fn test_different_pixels() {
+        let png = testing_png(16, 16, 2, 0);
+        let mut pixels = Pixels::decode(&png).unwrap();
+        pixels.data_mut()[0] ^= 1;
+        let mut changed = testing_png(16, 16, 2, 0);
+        pixels.encode(&mut changed).unwrap();
+        assert_ne!(pixel_digest(&png).unwrap(), pixel_digest(&changed).unwrap());
+    }
+}
@@ -0,0 +1,127 @@
+//
+// author: xigang zhao
+//
+
+// Encodes arbitrary bytes as prose-like text, one word per byte, so that a
+// payload stored in a text chunk reads like an ordinary comment.
+
+use crate::png_error::PngError;
+
+const WORDS: [&str; 256] = [
+    "about",  "above",  "across", "after",  "again",  "against", "along",  "always",
+    "among",  "animal", "answer", "apple",  "around", "autumn",  "away",   "back",
+    "basket", "beach",  "became", "before", "began",  "behind",  "being",  "below",
+    "better", "between","beyond", "bird",   "black",  "blue",    "boat",   "body",
+    "book",   "bottle", "bread",  "bridge", "bright", "brother", "brown",  "build",
+    "busy",   "cabin",  "calm",   "camera", "candle", "carry",   "castle", "center",
+    "chair",  "change", "church", "circle", "city",   "clear",   "close",  "cloud",
+    "coast",  "coffee", "cold",   "color",  "corner", "country", "course", "cover",
+    "cream",  "cross",  "crowd",  "dance",  "dark",   "daughter","deep",   "desert",
+    "dinner", "distant","doctor", "door",   "double", "dream",   "during", "early",
+    "earth",  "easy",   "edge",   "empty",  "engine", "evening", "every",  "face",
+    "family", "famous", "farm",   "father", "field",  "final",   "finger", "fire",
+    "first",  "flower", "follow", "forest", "forward","free",    "fresh",  "friend",
+    "front",  "fruit",  "garden", "gather", "gentle", "glass",   "golden", "grass",
+    "great",  "green",  "ground", "group",  "guitar", "half",    "hand",   "happy",
+    "harbor", "heart",  "heavy",  "hidden", "high",   "hill",    "history","home",
+    "horse",  "hour",   "house",  "island", "journey","kitchen", "ladder", "lake",
+    "large",  "later",  "leaf",   "letter", "light",  "little",  "long",   "lucky",
+    "market", "meadow", "middle", "minute", "mirror", "moment",  "morning","mother",
+    "mountain","music", "narrow", "nature", "near",   "never",   "night",  "north",
+    "number", "ocean",  "office", "often",  "old",    "open",    "orange", "other",
+    "paper",  "party",  "path",   "people", "picture","place",   "plain",  "planet",
+    "pocket", "quiet",  "rain",   "rather", "ready",  "red",     "river",  "road",
+    "rock",   "room",   "round",  "sail",   "salt",   "sand",    "school", "season",
+    "second", "shadow", "shore",  "silver", "simple", "singer",  "sister", "sky",
+    "slow",   "small",  "smile",  "snow",   "soft",   "song",    "south",  "spring",
+    "square", "star",   "station","stone",  "story",  "street",  "strong", "summer",
+    "sunny",  "sweet",  "table",  "tall",   "teacher","thunder", "today",  "tomorrow",
+    "tower",  "town",   "travel", "tree",   "under",  "until",   "valley", "village",
+    "voice",  "walk",   "warm",   "water",  "wave",   "weather", "white",  "wide",
+    "wild",   "wind",   "window", "winter", "within", "wonder",  "wooden", "world",
+    "yellow", "young",  "zebra",  "bamboo", "canyon", "harvest", "lantern","velvet",
+];
+
+const SENTENCE_MIN_WORDS: usize = 5;
+
+pub fn encode(data: &[u8]) -> String {
+    let mut text = String::new();
+    let mut words_in_sentence = 0;
+    for (i, byte) in data.iter().enumerate() {
+        let word = WORDS[*byte as usize];
+        if words_in_sentence == 0 {
+            if i > 0 {
+                text.push(' ');
+            }
+            let mut chars = word.chars();
+            if let Some(first) = chars.next() {
+                text.push(first.to_ascii_uppercase());
+                text.push_str(chars.as_str());
+            }
+        } else {
+            text.push(' ');
+            text.push_str(word);
+        }
+        words_in_sentence += 1;
+        // vary sentence length with the data so the text doesn't look mechanical
+        let last = i + 1 == data.len();
+        if last || (words_in_sentence >= SENTENCE_MIN_WORDS && byte % 4 == 0) {
+            text.push('.');
+            words_in_sentence = 0;
+        } else if words_in_sentence >= SENTENCE_MIN_WORDS && byte % 7 == 0 {
+            text.push(',');
+        }
+    }
+    return text;
+}
+
+// The longest text `size` bytes can turn into: every byte a longest word,
+// followed by punctuation and a space.
+pub fn max_text_len(size: usize) -> usize {
+    let longest = WORDS.iter().map(|w| w.len()).max().unwrap();
+    return size * (longest + 2);
+}
+
+pub fn decode(text: &str) -> Result<Vec<u8>, PngError> {
+    let mut data: Vec<u8> = Vec::new();
+    for token in text.split_whitespace() {
+        let word = token.trim_matches(|c: char| !c.is_ascii_alphabetic())
+                        .to_ascii_lowercase();
+        match WORDS.iter().position(|&w| w == word) {
+            Some(idx) => data.push(idx as u8),
+            None => return Err(PngError::InvalidCoverText),
+        }
+    }
+    return Ok(data);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_words_are_unique() {
+        let set: HashSet<&str> = WORDS.iter().copied().collect();
+        assert_eq!(set.len(), WORDS.len());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data: Vec<u8> = (0..=255).collect();
+        let text = encode(&data);
+        assert_eq!(decode(&text).unwrap(), data);
+    }
+
+    #[test]
+    fn test_looks_like_sentences() {
+        let text = encode("Hello, World!".as_bytes());
+        assert!(text.chars().next().unwrap().is_ascii_uppercase());
+        assert!(text.ends_with('.'));
+    }
+
+    #[test]
+    fn test_unknown_word() {
+        assert!(decode("Garden river xylophone.").is_err());
+    }
+}
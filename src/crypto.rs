@@ -0,0 +1,374 @@
+//
+// author: xigang zhao
+//
+
+// Password encryption with room for a decoy. A sealed payload always has two
+// slots of the same size, in random order:
+//
+//     kind (1) | salt (16) | m cost (4) | t cost (4) | p cost (4) | slot | slot
+//     slot: nonce (12) | ChaCha20-Poly1305 of [length (4) | container | random]
+//
+// Each slot is encrypted under an Argon2id key derived from its own password,
+// and both are padded with random bytes to the larger of the two payloads, so
+// the tail of the smaller one doesn't show where the other payload ends.
+// Without a decoy the second slot is random bytes, which can't be told apart
+// from a ciphertext, so nobody can prove whether a second payload exists. The
+// header is bound to both ciphertexts as associated data.
+//
+// A payload for X25519 recipients is sealed once under a random content key,
+// which is wrapped for every recipient:
+//
+//     kind (1) | ephemeral key (32) | count (1) | wrapped key (48) * count | nonce (12) | ciphertext
+//
+// Each wrapping key is HKDF-SHA256 of the shared secret between the ephemeral
+// key and the recipient, salted with both public keys. The record doesn't say
+// who the recipients are; decode tries every wrapped key with its identity.
+//
+// Derived keys, content keys and plaintext buffers are wiped once used.
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
+use zeroize::Zeroizing;
+use crate::crypto_error::CryptoError;
+use crate::Result;
+
+pub const PASSWORD: u8 = 1;
+pub const RECIPIENTS: u8 = 2;
+
+const SALT_SIZE: usize = 16;
+const HEADER_SIZE: usize = 1 + SALT_SIZE + 12;
+const NONCE_SIZE: usize = 12;
+const TAG_SIZE: usize = 16;
+const LENGTH_SIZE: usize = 4;
+const KEY_SIZE: usize = 32;
+const WRAPPED_SIZE: usize = KEY_SIZE + TAG_SIZE;
+const RECIPIENTS_INFO: &[u8] = b"deepng recipient";
+// limits on what a sealed payload may ask decode to spend
+const MAX_M_COST: u32 = 1 << 20; // KiB
+const MAX_T_COST: u32 = 16;
+const MAX_P_COST: u32 = 16;
+
+// What decode has to open a sealed payload with.
+#[derive(Default)]
+pub struct Credentials<'a> {
+    pub password: Option<&'a str>,
+    pub identity: Option<&'a StaticSecret>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    pub m_cost: u32, // memory in KiB
+    pub t_cost: u32, // passes
+    pub p_cost: u32, // lanes
+}
+
+impl Default for KdfParams {
+    fn default() -> KdfParams {
+        return KdfParams {
+            m_cost: Params::DEFAULT_M_COST,
+            t_cost: Params::DEFAULT_T_COST,
+            p_cost: Params::DEFAULT_P_COST,
+        };
+    }
+}
+
+// Size of the sealed form of a container of `size` bytes, when it is the
+// larger of the two.
+pub fn sealed_size(size: usize) -> usize {
+    return HEADER_SIZE + 2 * slot_size(size);
+}
+
+// Size of a container of `size` bytes sealed for `count` recipients.
+pub fn recipients_size(size: usize, count: usize) -> usize {
+    return 1 + KEY_SIZE + 1 + count * WRAPPED_SIZE + NONCE_SIZE + size + TAG_SIZE;
+}
+
+fn slot_size(size: usize) -> usize {
+    return NONCE_SIZE + LENGTH_SIZE + size + TAG_SIZE;
+}
+
+fn derive_key(password: &str, salt: &[u8], params: &KdfParams) -> Result<Zeroizing<[u8; 32]>> {
+    if params.m_cost > MAX_M_COST || params.t_cost > MAX_T_COST || params.p_cost > MAX_P_COST {
+        return Err(Box::from(CryptoError::InvalidKdfParams));
+    }
+    let params = Params::new(params.m_cost, params.t_cost, params.p_cost, Some(32))
+                        .map_err(|_| CryptoError::InvalidKdfParams)?;
+    let mut key = Zeroizing::new([0u8; 32]);
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+           .hash_password_into(password.as_bytes(), salt, key.as_mut())
+           .map_err(|_| CryptoError::InvalidKdfParams)?;
+    return Ok(key);
+}
+
+fn seal_slot(container: &[u8], size: usize, password: &str, header: &[u8], params: &KdfParams) -> Result<Vec<u8>> {
+    let key = derive_key(password, &header[1..1 + SALT_SIZE], params)?;
+    let mut plaintext = Zeroizing::new((container.len() as u32).to_be_bytes().to_vec());
+    plaintext.extend_from_slice(container);
+    // random, not zeros: a zero tail would show the other slot is larger
+    plaintext.extend((container.len()..size).map(|_| rand::random::<u8>()));
+    let nonce: [u8; NONCE_SIZE] = rand::random();
+    let ciphertext = ChaCha20Poly1305::new(Key::from_slice(key.as_ref()))
+                         .encrypt(Nonce::from_slice(&nonce), Payload { msg: &plaintext, aad: header })
+                         .map_err(|_| CryptoError::InvalidSealedPayload)?;
+    let mut slot = nonce.to_vec();
+    slot.extend_from_slice(&ciphertext);
+    return Ok(slot);
+}
+
+// Seals a packed payload under `password`, with an optional decoy container
+// that opens with a different password.
+pub fn seal(container: &[u8], password: &str, decoy: Option<(&[u8], &str)>, params: &KdfParams) -> Result<Vec<u8>> {
+    if let Some((_, decoy_password)) = decoy && decoy_password == password {
+        return Err(Box::from(CryptoError::SamePassword));
+    }
+    let size = container.len().max(decoy.map_or(0, |(d, _)| d.len()));
+    let mut header = vec![PASSWORD];
+    header.extend_from_slice(&rand::random::<[u8; SALT_SIZE]>());
+    for cost in [params.m_cost, params.t_cost, params.p_cost] {
+        header.extend_from_slice(&cost.to_be_bytes());
+    }
+    let real = seal_slot(container, size, password, &header, params)?;
+    let other = match decoy {
+        Some((decoy, decoy_password)) => seal_slot(decoy, size, decoy_password, &header, params)?,
+        None => (0..slot_size(size)).map(|_| rand::random::<u8>()).collect(),
+    };
+    let mut sealed = header;
+    if rand::random::<bool>() {
+        sealed.extend_from_slice(&real);
+        sealed.extend_from_slice(&other);
+    } else {
+        sealed.extend_from_slice(&other);
+        sealed.extend_from_slice(&real);
+    }
+    return Ok(sealed);
+}
+
+// Returns the container in whichever slot `password` opens.
+pub fn open(sealed: &[u8], password: &str) -> Result<Vec<u8>> {
+    if sealed.len() < HEADER_SIZE + 2 * slot_size(0) || !(sealed.len() - HEADER_SIZE).is_multiple_of(2) {
+        return Err(Box::from(CryptoError::InvalidSealedPayload));
+    }
+    if sealed[0] != PASSWORD {
+        return Err(Box::from(CryptoError::UnsupportedKind(sealed[0])));
+    }
+    let (header, slots) = sealed.split_at(HEADER_SIZE);
+    let cost = |i: usize| u32::from_be_bytes(header[1 + SALT_SIZE + 4 * i..][..4].try_into().unwrap());
+    let params = KdfParams { m_cost: cost(0), t_cost: cost(1), p_cost: cost(2) };
+    let key = derive_key(password, &header[1..1 + SALT_SIZE], &params)?;
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key.as_ref()));
+    for slot in slots.chunks(slots.len() / 2) {
+        let (nonce, ciphertext) = slot.split_at(NONCE_SIZE);
+        if let Ok(plaintext) = cipher.decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: header }).map(Zeroizing::new) {
+            let length = u32::from_be_bytes(plaintext[..LENGTH_SIZE].try_into().unwrap()) as usize;
+            let container = plaintext.get(LENGTH_SIZE..LENGTH_SIZE + length)
+                                     .ok_or(CryptoError::InvalidSealedPayload)?;
+            return Ok(container.to_vec());
+        }
+    }
+    return Err(Box::from(CryptoError::WrongPassword));
+}
+
+// Opens a sealed payload of either kind with the credential it needs.
+pub fn unseal(sealed: &[u8], credentials: &Credentials) -> Result<Vec<u8>> {
+    return match sealed.first() {
+        Some(&PASSWORD) => open(sealed, credentials.password.ok_or(CryptoError::PasswordRequired)?),
+        Some(&RECIPIENTS) => open_for(sealed, credentials.identity.ok_or(CryptoError::IdentityRequired)?),
+        Some(&kind) => Err(Box::from(CryptoError::UnsupportedKind(kind))),
+        None => Err(Box::from(CryptoError::InvalidSealedPayload)),
+    };
+}
+
+fn wrapping_key(secret: &StaticSecret, public: &PublicKey, ephemeral: &PublicKey, recipient: &PublicKey) -> Result<Zeroizing<[u8; KEY_SIZE]>> {
+    let shared = secret.diffie_hellman(public);
+    // a low-order key would give a shared secret anybody can compute
+    if !shared.was_contributory() {
+        return Err(Box::from(CryptoError::InvalidKey));
+    }
+    let mut salt = ephemeral.as_bytes().to_vec();
+    salt.extend_from_slice(recipient.as_bytes());
+    let mut key = Zeroizing::new([0u8; KEY_SIZE]);
+    Hkdf::<Sha256>::new(Some(&salt), shared.as_bytes())
+                   .expand(RECIPIENTS_INFO, key.as_mut())
+                   .map_err(|_| CryptoError::InvalidKey)?;
+    return Ok(key);
+}
+
+// Seals a packed payload so that the identity of any of `recipients` opens it.
+pub fn seal_for(container: &[u8], recipients: &[PublicKey]) -> Result<Vec<u8>> {
+    if recipients.is_empty() || recipients.len() > u8::MAX as usize {
+        return Err(Box::from(CryptoError::RecipientCount(recipients.len())));
+    }
+    let ephemeral = StaticSecret::from(rand::random::<[u8; KEY_SIZE]>());
+    let ephemeral_public = PublicKey::from(&ephemeral);
+    let content_key = Zeroizing::new(rand::random::<[u8; KEY_SIZE]>());
+    let mut header = vec![RECIPIENTS];
+    header.extend_from_slice(ephemeral_public.as_bytes());
+    header.push(recipients.len() as u8);
+    for recipient in recipients {
+        let key = wrapping_key(&ephemeral, recipient, &ephemeral_public, recipient)?;
+        // every wrapping key is used once, so a fixed nonce is safe
+        let wrapped = ChaCha20Poly1305::new(Key::from_slice(key.as_ref()))
+                          .encrypt(&Nonce::default(), content_key.as_ref())
+                          .map_err(|_| CryptoError::InvalidSealedPayload)?;
+        header.extend_from_slice(&wrapped);
+    }
+    let nonce: [u8; NONCE_SIZE] = rand::random();
+    let ciphertext = ChaCha20Poly1305::new(Key::from_slice(content_key.as_ref()))
+                         .encrypt(Nonce::from_slice(&nonce), Payload { msg: container, aad: &header })
+                         .map_err(|_| CryptoError::InvalidSealedPayload)?;
+    let mut sealed = header;
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+    return Ok(sealed);
+}
+
+// Returns the container of a payload sealed for recipients, if `identity` is
+// one of them.
+pub fn open_for(sealed: &[u8], identity: &StaticSecret) -> Result<Vec<u8>> {
+    if sealed.len() < recipients_size(0, 0) {
+        return Err(Box::from(CryptoError::InvalidSealedPayload));
+    }
+    if sealed[0] != RECIPIENTS {
+        return Err(Box::from(CryptoError::UnsupportedKind(sealed[0])));
+    }
+    let count = sealed[1 + KEY_SIZE] as usize;
+    if sealed.len() < recipients_size(0, count) {
+        return Err(Box::from(CryptoError::InvalidSealedPayload));
+    }
+    let (header, rest) = sealed.split_at(1 + KEY_SIZE + 1 + count * WRAPPED_SIZE);
+    let ephemeral = PublicKey::from(<[u8; KEY_SIZE]>::try_from(&header[1..1 + KEY_SIZE]).unwrap());
+    let key = wrapping_key(identity, &ephemeral, &ephemeral, &PublicKey::from(identity))?;
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key.as_ref()));
+    let content_key = header[1 + KEY_SIZE + 1..].chunks(WRAPPED_SIZE)
+                            .find_map(|wrapped| cipher.decrypt(&Nonce::default(), wrapped).ok())
+                            .map(Zeroizing::new)
+                            .ok_or(CryptoError::NotARecipient)?;
+    let (nonce, ciphertext) = rest.split_at(NONCE_SIZE);
+    let container = ChaCha20Poly1305::new(Key::from_slice(&content_key))
+                        .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: header })
+                        .map_err(|_| CryptoError::InvalidSealedPayload)?;
+    return Ok(container);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // cheap enough for unoptimized test builds
+    const PARAMS: KdfParams = KdfParams { m_cost: 64, t_cost: 1, p_cost: 1 };
+
+    #[test]
+    fn test_round_trip() {
+        let sealed = seal(b"the real payload", "correct horse", None, &PARAMS).unwrap();
+        assert_eq!(sealed.len(), sealed_size(16));
+        assert_eq!(open(&sealed, "correct horse").unwrap(), b"the real payload");
+        assert!(matches!(open(&sealed, "battery staple").unwrap_err().downcast_ref::<CryptoError>(),
+                         Some(CryptoError::WrongPassword)));
+    }
+
+    #[test]
+    fn test_decoy() {
+        let sealed = seal(b"the real payload", "real", Some((b"shopping list", "decoy")), &PARAMS).unwrap();
+        assert_eq!(sealed.len(), sealed_size(16));
+        assert_eq!(open(&sealed, "real").unwrap(), b"the real payload");
+        assert_eq!(open(&sealed, "decoy").unwrap(), b"shopping list");
+        assert!(open(&sealed, "other").is_err());
+    }
+
+    #[test]
+    fn test_same_size_with_and_without_decoy() {
+        let plain = seal(b"payload", "real", None, &PARAMS).unwrap();
+        let decoy = seal(b"payload", "real", Some((b"payload", "decoy")), &PARAMS).unwrap();
+        assert_eq!(plain.len(), decoy.len());
+        assert!(seal(b"payload", "same", Some((b"other", "same")), &PARAMS).is_err());
+    }
+
+    // The whole plaintext of the slot `password` opens, padding included.
+    fn open_slot(sealed: &[u8], password: &str) -> Vec<u8> {
+        let (header, slots) = sealed.split_at(HEADER_SIZE);
+        let key = derive_key(password, &header[1..1 + SALT_SIZE], &PARAMS).unwrap();
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(key.as_ref()));
+        return slots.chunks(slots.len() / 2)
+                    .find_map(|slot| cipher.decrypt(Nonce::from_slice(&slot[..NONCE_SIZE]),
+                                                    Payload { msg: &slot[NONCE_SIZE..], aad: header }).ok())
+                    .unwrap();
+    }
+
+    #[test]
+    fn test_slot_padding() {
+        let real = [7u8; 200];
+        let sealed = seal(&real, "real", Some((b"shopping list", "decoy")), &PARAMS).unwrap();
+        let decoy = open_slot(&sealed, "decoy");
+        let tail = &decoy[LENGTH_SIZE + 13..];
+        assert_eq!(tail.len(), 187);
+        // no run of zeros marks where the real payload would end
+        assert!(tail.windows(8).all(|w| w.iter().any(|&b| b != 0)));
+
+        let sealed = seal(b"shopping list", "real", Some((&real, "decoy")), &PARAMS).unwrap();
+        let tail = &open_slot(&sealed, "real")[LENGTH_SIZE + 13..];
+        assert!(tail.windows(8).all(|w| w.iter().any(|&b| b != 0)));
+    }
+
+    #[test]
+    fn test_tampered() {
+        let mut sealed = seal(b"payload", "real", None, &PARAMS).unwrap();
+        // the header is authenticated along with the slots
+        sealed[1] ^= 1;
+        assert!(open(&sealed, "real").is_err());
+        assert!(open(&sealed[..10], "real").is_err());
+    }
+
+    #[test]
+    fn test_recipients() {
+        let alice = StaticSecret::from([1u8; KEY_SIZE]);
+        let bob = StaticSecret::from([2u8; KEY_SIZE]);
+        let eve = StaticSecret::from([3u8; KEY_SIZE]);
+        let recipients = [PublicKey::from(&alice), PublicKey::from(&bob)];
+        let sealed = seal_for(b"for alice and bob", &recipients).unwrap();
+        assert_eq!(sealed.len(), recipients_size(17, 2));
+        assert_eq!(open_for(&sealed, &alice).unwrap(), b"for alice and bob");
+        assert_eq!(open_for(&sealed, &bob).unwrap(), b"for alice and bob");
+        assert!(matches!(open_for(&sealed, &eve).unwrap_err().downcast_ref::<CryptoError>(),
+                         Some(CryptoError::NotARecipient)));
+        assert!(seal_for(b"nobody", &[]).is_err());
+    }
+
+    #[test]
+    fn test_recipients_tampered() {
+        let alice = StaticSecret::from([1u8; KEY_SIZE]);
+        let mut sealed = seal_for(b"payload", &[PublicKey::from(&alice)]).unwrap();
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+        assert!(open_for(&sealed, &alice).is_err());
+        // a count pointing past the end
+        sealed[1 + KEY_SIZE] = 200;
+        assert!(open_for(&sealed, &alice).is_err());
+    }
+
+    #[test]
+    fn test_unseal() {
+        let alice = StaticSecret::from([1u8; KEY_SIZE]);
+        let for_alice = seal_for(b"payload", &[PublicKey::from(&alice)]).unwrap();
+        let with_password = seal(b"payload", "pw", None, &PARAMS).unwrap();
+        let password = Credentials { password: Some("pw"), identity: None };
+        let identity = Credentials { password: None, identity: Some(&alice) };
+        assert_eq!(unseal(&for_alice, &identity).unwrap(), b"payload");
+        assert_eq!(unseal(&with_password, &password).unwrap(), b"payload");
+        assert!(matches!(unseal(&for_alice, &password).unwrap_err().downcast_ref::<CryptoError>(),
+                         Some(CryptoError::IdentityRequired)));
+        assert!(matches!(unseal(&with_password, &identity).unwrap_err().downcast_ref::<CryptoError>(),
+                         Some(CryptoError::PasswordRequired)));
+    }
+
+    #[test]
+    fn test_excessive_cost() {
+        let mut sealed = seal(b"payload", "real", None, &PARAMS).unwrap();
+        sealed[1 + SALT_SIZE..1 + SALT_SIZE + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(open(&sealed, "real").unwrap_err().downcast_ref::<CryptoError>(),
+                         Some(CryptoError::InvalidKdfParams)));
+    }
+}
@@ -0,0 +1,38 @@
+//
+// author: xigang zhao
+//
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum CryptoError {
+    InvalidSealedPayload,
+    UnsupportedKind(u8),
+    InvalidKdfParams,
+    SamePassword,
+    WrongPassword,
+    PasswordRequired,
+    IdentityRequired,
+    NotARecipient,
+    RecipientCount(usize),
+    InvalidKey,
+}
+
+impl fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CryptoError::InvalidSealedPayload => write!(f, "Truncated or malformed encrypted payload"),
+            CryptoError::UnsupportedKind(kind) => write!(f, "Unsupported kind of encryption ({})", kind),
+            CryptoError::InvalidKdfParams     => write!(f, "Invalid or excessive key derivation parameters"),
+            CryptoError::SamePassword         => write!(f, "The decoy needs a different password"),
+            CryptoError::WrongPassword        => write!(f, "No payload opens with this password"),
+            CryptoError::PasswordRequired     => write!(f, "This payload is encrypted, give --password"),
+            CryptoError::IdentityRequired     => write!(f, "This payload is encrypted for recipients, give --identity"),
+            CryptoError::NotARecipient        => write!(f, "This identity is not one of the recipients"),
+            CryptoError::RecipientCount(n)    => write!(f, "Between 1 and 255 recipients are supported, not {}", n),
+            CryptoError::InvalidKey           => write!(f, "Invalid X25519 identity or recipient key"),
+        }
+    }
+}
+
+impl std::error::Error for CryptoError {}
@@ -0,0 +1,411 @@
+//
+// author: xigang zhao
+//
+
+// Chunk-level comparison of two PNGs. The chunk lists are lined up on their
+// longest common subsequence; what's left is paired up as moved (same body
+// at another position) or modified (same type, different body) before being
+// reported as removed or added.
+
+use std::convert::TryFrom;
+use std::fmt;
+use crate::chunk::Chunk;
+use crate::pixels::Pixels;
+use crate::png::Png;
+use crate::text_chunk::TextChunk;
+use crate::Result;
+
+// Bodies up to this size get a byte-level diff.
+const SMALL_CHUNK: usize = 256;
+const MAX_RANGES: usize = 16;
+// cells in the LCS table, 16 MB of u32
+const MAX_LCS_CELLS: usize = 1 << 22;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Unchanged,
+    Moved,
+    Modified,
+    Removed,
+    Added,
+}
+
+#[derive(Debug)]
+pub struct ChunkChange {
+    kind      : ChangeKind,
+    chunk_type: String,
+    before    : Option<(usize, u32)>, // index and length in the first file
+    after     : Option<(usize, u32)>, // index and length in the second file
+    details   : Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct Diff {
+    changes: Vec<ChunkChange>,
+    trailer: Option<(usize, usize, Vec<String>)>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct PixelDiff {
+    old_format: (u32, u32, u8, u8), // width, height, bit depth, color type
+    new_format: (u32, u32, u8, u8),
+    samples   : usize,
+    differing : usize,
+    max_delta : u16,
+    bounds    : Option<(u32, u32, u32, u32)>, // first and last changed x, y
+}
+
+impl fmt::Display for ChunkChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let marker = match self.kind {
+            ChangeKind::Unchanged => ' ',
+            ChangeKind::Moved     => '>',
+            ChangeKind::Modified  => '~',
+            ChangeKind::Removed   => '-',
+            ChangeKind::Added     => '+',
+        };
+        let index = |side: Option<(usize, u32)>| side.map_or(String::new(), |(i, _)| format!("#{}", i));
+        let size = match (self.before, self.after) {
+            (Some((_, a)), Some((_, b))) if a != b => format!("{} -> {}", a, b),
+            (Some((_, a)), _) | (None, Some((_, a))) => a.to_string(),
+            (None, None) => String::new(),
+        };
+        write!(f, "{} {:<5} {:<5} {} {:>8}", marker, index(self.before), index(self.after), self.chunk_type, size)?;
+        if self.kind == ChangeKind::Moved {
+            write!(f, "  (moved)")?;
+        }
+        for line in &self.details {
+            write!(f, "\n      {}", line)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let lines: Vec<String> = self.changes.iter().map(|c| c.to_string()).collect();
+        write!(f, "{}", lines.join("\n"))?;
+        if let Some((old, new, details)) = &self.trailer {
+            write!(f, "\n~ trailer {} -> {} bytes", old, new)?;
+            for line in details {
+                write!(f, "\n      {}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for PixelDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.old_format != self.new_format {
+            let (w0, h0, d0, c0) = self.old_format;
+            let (w1, h1, d1, c1) = self.new_format;
+            return write!(f, "pixels: {}x{} depth {} color type {} vs {}x{} depth {} color type {}, not compared",
+                          w0, h0, d0, c0, w1, h1, d1, c1);
+        }
+        match self.bounds {
+            None => write!(f, "pixels: identical"),
+            Some((x0, y0, x1, y1)) =>
+                write!(f, "pixels: {} of {} samples differ (max difference {}) within x {}..{}, y {}..{}",
+                       self.differing, self.samples, self.max_delta, x0, x1, y0, y1),
+        }
+    }
+}
+
+impl PixelDiff {
+    pub fn comparable(&self) -> bool {
+        return self.old_format == self.new_format;
+    }
+}
+
+pub fn diff(old: &Png, new: &Png) -> Diff {
+    let (a, b) = (old.chunks(), new.chunks());
+    let anchors = lcs(a, b);
+    let mut old_kind: Vec<Option<ChangeKind>> = vec![None; a.len()];
+    let mut new_kind: Vec<Option<ChangeKind>> = vec![None; b.len()];
+    let mut new_pair: Vec<Option<usize>> = vec![None; b.len()];
+    for &(i, j) in &anchors {
+        old_kind[i] = Some(ChangeKind::Unchanged);
+        new_kind[j] = Some(ChangeKind::Unchanged);
+    }
+    // identical bodies first, then whatever has the same type
+    for (kind, same) in [(ChangeKind::Moved, chunks_equal as fn(&Chunk, &Chunk) -> bool),
+                         (ChangeKind::Modified, |x: &Chunk, y: &Chunk| x.chunk_type() == y.chunk_type())] {
+        for j in 0..b.len() {
+            if new_kind[j].is_some() {
+                continue;
+            }
+            if let Some(i) = (0..a.len()).find(|&i| old_kind[i].is_none() && same(&a[i], &b[j])) {
+                old_kind[i] = Some(kind);
+                new_kind[j] = Some(kind);
+                new_pair[j] = Some(i);
+            }
+        }
+    }
+
+    // removed chunks are listed where they were, everything else where it is now
+    let mut changes: Vec<ChunkChange> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    for &(ai, bj) in anchors.iter().chain(std::iter::once(&(a.len(), b.len()))) {
+        for k in i..ai {
+            if old_kind[k].is_none() {
+                changes.push(change(ChangeKind::Removed, Some((k, &a[k])), None));
+            }
+        }
+        for k in j..bj {
+            let kind = new_kind[k].unwrap_or(ChangeKind::Added);
+            changes.push(change(kind, new_pair[k].map(|p| (p, &a[p])), Some((k, &b[k]))));
+        }
+        if ai < a.len() {
+            changes.push(change(ChangeKind::Unchanged, Some((ai, &a[ai])), Some((bj, &b[bj]))));
+        }
+        (i, j) = (ai + 1, bj + 1);
+    }
+    let trailer = match old.trailer() == new.trailer() {
+        true  => None,
+        false => Some((old.trailer().len(), new.trailer().len(), body_diff(old.trailer(), new.trailer()))),
+    };
+    return Diff { changes, trailer };
+}
+
+pub fn diff_pixels(old: &Png, new: &Png) -> Result<PixelDiff> {
+    let (a, b) = (Pixels::decode(old)?, Pixels::decode(new)?);
+    let format = |p: &Pixels| (p.header().width(), p.header().height(), p.header().bit_depth(), p.header().color_type());
+    let mut result = PixelDiff {
+        old_format: format(&a),
+        new_format: format(&b),
+        samples   : 0,
+        differing : 0,
+        max_delta : 0,
+        bounds    : None,
+    };
+    if !result.comparable() {
+        return Ok(result);
+    }
+    let (sa, sb) = (a.samples(), b.samples());
+    let per_pixel = a.header().channels();
+    let width = a.header().width() as usize;
+    result.samples = sa.len();
+    for (n, (x, y)) in sa.iter().zip(&sb).enumerate() {
+        if x == y {
+            continue;
+        }
+        result.differing += 1;
+        result.max_delta = result.max_delta.max(x.abs_diff(*y));
+        let (px, py) = (((n / per_pixel) % width) as u32, ((n / per_pixel) / width) as u32);
+        result.bounds = Some(match result.bounds {
+            None => (px, py, px, py),
+            Some((x0, y0, x1, y1)) => (x0.min(px), y0.min(py), x1.max(px), y1.max(py)),
+        });
+    }
+    return Ok(result);
+}
+
+fn chunks_equal(a: &Chunk, b: &Chunk) -> bool {
+    return a.chunk_type() == b.chunk_type() && a.data() == b.data();
+}
+
+fn change(kind: ChangeKind, old: Option<(usize, &Chunk)>, new: Option<(usize, &Chunk)>) -> ChunkChange {
+    let chunk_type = old.or(new).unwrap().1.chunk_type().to_string();
+    let details = match (kind, old, new) {
+        (ChangeKind::Modified, Some((_, a)), Some((_, b))) => chunk_details(a, b),
+        _ => Vec::new(),
+    };
+    return ChunkChange {
+        kind,
+        chunk_type,
+        before: old.map(|(i, ck)| (i, ck.length())),
+        after : new.map(|(i, ck)| (i, ck.length())),
+        details,
+    };
+}
+
+fn chunk_details(a: &Chunk, b: &Chunk) -> Vec<String> {
+    if let (Ok(ta), Ok(tb)) = (TextChunk::try_from(a), TextChunk::try_from(b)) {
+        let mut details: Vec<String> = Vec::new();
+        if ta.keyword() != tb.keyword() {
+            details.push(format!("keyword \"{}\" -> \"{}\"", ta.keyword(), tb.keyword()));
+        }
+        details.extend(text_diff(ta.text(), tb.text()));
+        return details;
+    }
+    return body_diff(a.data(), b.data());
+}
+
+fn text_diff(a: &str, b: &str) -> Vec<String> {
+    let (la, lb): (Vec<&str>, Vec<&str>) = (a.lines().collect(), b.lines().collect());
+    let mut details: Vec<String> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    for (ai, bj) in lcs(&la, &lb).into_iter().chain(std::iter::once((la.len(), lb.len()))) {
+        details.extend(la[i..ai].iter().map(|line| format!("- {}", line)));
+        details.extend(lb[j..bj].iter().map(|line| format!("+ {}", line)));
+        (i, j) = (ai + 1, bj + 1);
+    }
+    return details;
+}
+
+// Runs of differing bytes as "@offset: old -> new" in hex.
+fn body_diff(a: &[u8], b: &[u8]) -> Vec<String> {
+    let common = a.len().min(b.len());
+    let differing = (0..common).filter(|&k| a[k] != b[k]).count() + a.len().max(b.len()) - common;
+    if a.len().max(b.len()) > SMALL_CHUNK {
+        return vec![format!("{} bytes differ", differing)];
+    }
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for k in 0..a.len().max(b.len()) {
+        if a.get(k) == b.get(k) {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some((_, end)) if *end == k => *end = k + 1,
+            _ => ranges.push((k, k + 1)),
+        }
+    }
+    let mut details: Vec<String> = ranges.iter().take(MAX_RANGES).map(|&(start, end)| {
+        let part = |data: &[u8]| hex::encode(&data[start.min(data.len())..end.min(data.len())]);
+        format!("@{}: {} -> {}", start, part(a), part(b))
+    }).collect();
+    if ranges.len() > MAX_RANGES {
+        details.push(format!("... {} more", ranges.len() - MAX_RANGES));
+    }
+    return details;
+}
+
+// Index pairs of a longest common subsequence, in order. The common prefix
+// and suffix are matched as they are, and only what lies between goes through
+// the quadratic table; if that would take more than MAX_LCS_CELLS, nothing in
+// between is matched and all of it shows as changed.
+fn lcs<T: PartialEq>(a: &[T], b: &[T]) -> Vec<(usize, usize)> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..].iter().rev().zip(b[prefix..].iter().rev()).take_while(|(x, y)| x == y).count();
+    let (middle_a, middle_b) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+    let mut pairs: Vec<(usize, usize)> = (0..prefix).map(|k| (k, k)).collect();
+    if (middle_a.len() + 1).saturating_mul(middle_b.len() + 1) <= MAX_LCS_CELLS {
+        pairs.extend(lcs_table(middle_a, middle_b).into_iter().map(|(i, j)| (prefix + i, prefix + j)));
+    }
+    pairs.extend((0..suffix).map(|k| (a.len() - suffix + k, b.len() - suffix + k)));
+    return pairs;
+}
+
+fn lcs_table<T: PartialEq>(a: &[T], b: &[T]) -> Vec<(usize, usize)> {
+    let (n, m) = (a.len(), b.len());
+    let mut table = vec![vec![0u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            table[i][j] = if a[i] == b[j] {
+                table[i + 1][j + 1] + 1
+            } else {
+                table[i + 1][j].max(table[i][j + 1])
+            };
+        }
+    }
+    let mut pairs: Vec<(usize, usize)> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if a[i] == b[j] {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if table[i + 1][j] >= table[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    return pairs;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::chunk_type::ChunkType;
+    use crate::pixels::tests::testing_png;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn kinds(diff: &Diff) -> Vec<(ChangeKind, &str)> {
+        diff.changes.iter().map(|c| (c.kind, c.chunk_type.as_str())).collect()
+    }
+
+    #[test]
+    fn test_identical() {
+        let png = testing_png(8, 8, 2, 0);
+        let diff = diff(&png, &testing_png(8, 8, 2, 0));
+        assert!(diff.trailer.is_none());
+        assert!(diff.changes.iter().all(|c| c.kind == ChangeKind::Unchanged));
+        assert_eq!(diff.changes.len(), 3);
+    }
+
+    #[test]
+    fn test_changes() {
+        let mut old = testing_png(8, 8, 2, 0);
+        old.insert_chunk(1, chunk("gAMA", &[0, 0, 177, 143]));
+        old.insert_chunk(2, chunk("tEXt", b"Comment\0one\ntwo"));
+        old.insert_chunk(3, chunk("tIME", &[7, 234, 1, 1, 0, 0, 0]));
+        let mut new = testing_png(8, 8, 2, 0);
+        new.insert_chunk(1, chunk("tEXt", b"Comment\0one\nthree"));
+        new.insert_chunk(3, chunk("gAMA", &[0, 0, 177, 143]));
+        new.insert_chunk(4, chunk("ruSt", b"hidden"));
+        let diff = diff(&old, &new);
+        assert_eq!(kinds(&diff), vec![
+            (ChangeKind::Unchanged, "IHDR"),
+            (ChangeKind::Removed, "tIME"),
+            (ChangeKind::Modified, "tEXt"),
+            (ChangeKind::Unchanged, "IDAT"),
+            (ChangeKind::Moved, "gAMA"),
+            (ChangeKind::Added, "ruSt"),
+            (ChangeKind::Unchanged, "IEND"),
+        ]);
+        assert_eq!(diff.changes[2].details, ["- two", "+ three"]);
+        assert_eq!(diff.changes[4].before, Some((1, 4)));
+    }
+
+    #[test]
+    fn test_body_diff() {
+        assert_eq!(body_diff(&[1, 2, 3, 4], &[1, 9, 9, 4, 5]), vec!["@1: 0203 -> 0909", "@4:  -> 05"]);
+        assert_eq!(body_diff(&[0; 300], &[1; 300]), vec!["300 bytes differ"]);
+    }
+
+    #[test]
+    fn test_lcs() {
+        assert_eq!(lcs(b"abcxdef", b"abydef"), vec![(0, 0), (1, 1), (4, 3), (5, 4), (6, 5)]);
+        assert_eq!(lcs(b"axbyc", b"xaybc").len(), 3);
+        // too far apart for the table: only the common ends are matched
+        let a: Vec<u32> = (0..5000).collect();
+        let mut b: Vec<u32> = a.iter().map(|x| x + 10000).collect();
+        b[0] = 0;
+        b[4999] = 4999;
+        assert_eq!(lcs(&a, &b), vec![(0, 0), (4999, 4999)]);
+        // a long text with one changed line
+        let mut lines: Vec<u32> = (0..100000).collect();
+        let before = lines.clone();
+        lines[50000] = 0;
+        assert_eq!(lcs(&before, &lines).len(), 99999);
+    }
+
+    #[test]
+    fn test_trailer() {
+        let old = testing_png(8, 8, 2, 0);
+        let mut new = testing_png(8, 8, 2, 0);
+        new.set_trailer(b"more".to_vec());
+        assert_eq!(diff(&old, &new).trailer.map(|(old, new, _)| (old, new)), Some((0, 4)));
+    }
+
+    #[test]
+    fn test_pixels() {
+        let old = testing_png(8, 8, 2, 0);
+        let mut new = testing_png(8, 8, 2, 1);
+        assert_eq!(diff_pixels(&old, &new).unwrap().bounds, None);
+        let mut pixels = Pixels::decode(&new).unwrap();
+        pixels.data_mut()[3 * 24 + 7] ^= 1;
+        pixels.encode(&mut new).unwrap();
+        let result = diff_pixels(&old, &new).unwrap();
+        assert_eq!(result.differing, 1);
+        assert_eq!(result.max_delta, 1);
+        assert_eq!(result.bounds, Some((2, 3, 2, 3)));
+        assert!(!diff_pixels(&old, &testing_png(4, 8, 2, 0)).unwrap().comparable());
+    }
+}
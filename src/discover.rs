@@ -0,0 +1,209 @@
+//
+// author: xigang zhao
+//
+
+// Finds deepng payloads without knowing how they were embedded, by looking
+// for a container in every place an embedding method can write to: chunks,
+// text, the trailer and APNG frame controls. Pixels aren't searched, since
+// no encode method writes there.
+
+use std::convert::TryFrom;
+use std::fmt;
+use crate::container::{self, Payload};
+use crate::png::Png;
+use crate::text_chunk::TextChunk;
+use crate::{apng, cover_text, fctl_stego};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    Chunk(usize), // the data of the chunk at this index
+    Text(usize),  // the cover text of the text chunk at this index
+    Trailer,
+    FrameControls,
+}
+
+#[derive(Debug)]
+pub struct Found {
+    location   : Location,
+    description: String,
+    raw        : Vec<u8>,
+    payload    : Result<Payload, String>,
+}
+
+impl fmt::Display for Found {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: ", self.description)?;
+        let payload = match &self.payload {
+            Ok(payload) => payload,
+            Err(e) => return write!(f, "damaged ({})", e),
+        };
+        write!(f, "{}", payload.name().unwrap_or("message"))?;
+        if let Some(mime_type) = payload.mime_type() {
+            write!(f, " ({})", mime_type)?;
+        }
+        write!(f, ", {} bytes", payload.data().len())?;
+        let (major, minor) = payload.version();
+        write!(f, ", format {}.{}", major, minor)?;
+        if payload.flags() & container::COMPRESSED != 0 {
+            write!(f, ", compressed")?;
+        }
+        if payload.flags() & container::ENCRYPTED != 0 {
+            write!(f, ", encrypted")?;
+        }
+        if payload.flags() & container::FRAGMENTED != 0 {
+            write!(f, ", fragment")?;
+        }
+        if payload.flags() & container::SHARE != 0 {
+            write!(f, ", share")?;
+        }
+        if payload.flags() & container::FEC != 0 {
+            write!(f, ", error correction")?;
+        }
+        Ok(())
+    }
+}
+
+impl Found {
+    pub fn location(&self) -> Location {
+        return self.location;
+    }
+
+    // The container as it was found, before unpacking.
+    pub fn raw(&self) -> &[u8] {
+        return &self.raw;
+    }
+
+    pub fn payload(&self) -> Result<&Payload, &str> {
+        return self.payload.as_ref().map_err(|e| e.as_str());
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        return self.payload.as_ref().ok().and_then(|p| p.name());
+    }
+}
+
+pub fn discover(png: &Png) -> Vec<Found> {
+    let mut found: Vec<Found> = Vec::new();
+    for (i, ck) in png.chunks().iter().enumerate() {
+        if ck.chunk_type().is_critical() {
+            continue;
+        }
+        if container::is_container(ck.data()) {
+            let description = format!("chunk #{} {}", i, ck.chunk_type());
+            found.push(candidate(Location::Chunk(i), description, ck.data().to_vec()));
+        } else if let Ok(tc) = TextChunk::try_from(ck)
+            && let Ok(data) = cover_text::decode(tc.text())
+            && container::is_container(&data) {
+            let description = format!("text chunk #{} {} \"{}\"", i, ck.chunk_type(), tc.keyword());
+            found.push(candidate(Location::Text(i), description, data));
+        }
+    }
+    if container::is_container(png.trailer()) {
+        found.push(candidate(Location::Trailer, "data after IEND".to_string(), png.trailer().to_vec()));
+    }
+    if apng::is_animated(png) && let Ok(data) = fctl_stego::extract(png) && container::is_container(&data) {
+        found.push(candidate(Location::FrameControls, "APNG frame controls".to_string(), data));
+    }
+    return found;
+}
+
+// Takes the carrier of a payload out of the image, as decode does when the
+// method is given. Frame controls stay, since the animation needs them.
+pub fn remove(png: &mut Png, location: Location) {
+    match location {
+        Location::Chunk(i) | Location::Text(i) => {
+            png.remove_chunk(i);
+        }
+        Location::Trailer => {
+            png.take_trailer();
+        }
+        Location::FrameControls => {}
+    }
+}
+
+// The last payload stored under `name`.
+pub fn find_by_name(png: &Png, name: &str) -> Option<Found> {
+    return discover(png).into_iter().rfind(|f| f.name() == Some(name));
+}
+
+// Removes every payload stored under `name` and returns how many there were.
+pub fn remove_by_name(png: &mut Png, name: &str) -> usize {
+    let found: Vec<Found> = discover(png).into_iter().filter(|f| f.name() == Some(name)).collect();
+    // back to front, so the earlier chunk indices stay valid
+    for f in found.iter().rev() {
+        remove(png, f.location());
+    }
+    return found.len();
+}
+
+fn candidate(location: Location, description: String, raw: Vec<u8>) -> Found {
+    let payload = container::unpack(&raw).map_err(|e| e.to_string());
+    return Found { location, description, raw, payload };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use crate::container::PackOptions;
+    use crate::pixels::tests::testing_png;
+    use crate::text_chunk::TextKind;
+
+    fn packed(data: &[u8]) -> Vec<u8> {
+        container::pack(data, &PackOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn test_discover() {
+        let mut png = testing_png(8, 8, 2, 0);
+        png.insert_chunk(1, Chunk::new(ChunkType::from_str("ruSt").unwrap(), packed(b"one")));
+        png.insert_chunk(1, Chunk::new(ChunkType::from_str("teSt").unwrap(), b"not a payload".to_vec()));
+        let text = cover_text::encode(&packed(b"two"));
+        png.insert_chunk(3, TextChunk::new(TextKind::International, "Comment", &text).unwrap().to_chunk());
+        png.set_trailer(packed(b"three"));
+        let found = discover(&png);
+        let locations: Vec<Location> = found.iter().map(|f| f.location()).collect();
+        assert_eq!(locations, vec![Location::Chunk(2), Location::Text(3), Location::Trailer]);
+        assert_eq!(found[1].payload().unwrap().data(), b"two");
+        assert_eq!(found[0].to_string(), "chunk #2 ruSt: message, 3 bytes, format 1.0");
+    }
+
+    #[test]
+    fn test_names() {
+        let named = |name: &str, data: &[u8]| {
+            container::pack(data, &PackOptions { name: Some(name.to_string()), ..Default::default() }).unwrap()
+        };
+        let mut png = testing_png(8, 8, 2, 0);
+        png.insert_chunk(1, Chunk::new(ChunkType::from_str("ruSt").unwrap(), named("a", b"old")));
+        png.insert_chunk(2, Chunk::new(ChunkType::from_str("ruSt").unwrap(), named("b", b"other")));
+        png.insert_chunk(3, Chunk::new(ChunkType::from_str("ruSt").unwrap(), named("a", b"new")));
+        assert_eq!(find_by_name(&png, "a").unwrap().payload().unwrap().data(), b"new");
+        assert!(find_by_name(&png, "c").is_none());
+        assert_eq!(remove_by_name(&mut png, "a"), 2);
+        assert_eq!(discover(&png).len(), 1);
+        assert_eq!(find_by_name(&png, "b").unwrap().location(), Location::Chunk(1));
+    }
+
+    #[test]
+    fn test_damaged() {
+        let mut png = testing_png(8, 8, 2, 0);
+        let mut data = packed(b"hello");
+        data.pop();
+        png.set_trailer(data);
+        let found = discover(&png);
+        assert_eq!(found.len(), 1);
+        assert!(found[0].payload().is_err());
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut png = testing_png(8, 8, 2, 0);
+        png.insert_chunk(1, Chunk::new(ChunkType::from_str("ruSt").unwrap(), packed(b"one")));
+        let location = discover(&png)[0].location();
+        remove(&mut png, location);
+        assert!(discover(&png).is_empty());
+        assert_eq!(png.chunks().len(), 3);
+    }
+}
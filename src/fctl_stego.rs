@@ -0,0 +1,259 @@
+//
+// author: xigang zhao
+//
+
+// Hides bits in fcTL fields whose value doesn't change what the viewer sees:
+// the scale of the delay fraction (1/10 vs 2/20), the blend op where
+// blending can't make a difference, and the dispose op where the next frame
+// paints over the whole canvas anyway.
+
+use std::convert::TryFrom;
+use crate::apng::{self, FrameControl};
+use crate::ihdr::ImageHeader;
+use crate::png::Png;
+use crate::png_error::PngError;
+use crate::Result;
+
+// Scaling a delay by more than this looks odd next to ordinary values.
+const MAX_DELAY_BITS: u32 = 3;
+const LENGTH_BITS: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameSlots {
+    frame      : usize,
+    chunk_index: usize,
+    delay_bits : u32,
+    blend      : bool,
+    dispose    : bool,
+}
+
+impl FrameSlots {
+    pub fn frame(&self) -> usize {
+        return self.frame;
+    }
+
+    pub fn delay_bits(&self) -> u32 {
+        return self.delay_bits;
+    }
+
+    pub fn blend(&self) -> bool {
+        return self.blend;
+    }
+
+    pub fn dispose(&self) -> bool {
+        return self.dispose;
+    }
+
+    pub fn bits(&self) -> usize {
+        return self.delay_bits as usize + self.blend as usize + self.dispose as usize;
+    }
+}
+
+pub fn slots(png: &Png) -> Result<Vec<FrameSlots>> {
+    if !apng::is_animated(png) {
+        return Err(Box::from(PngError::NotAnimated));
+    }
+    apng::validate(png)?;
+    let header = ImageHeader::try_from(png.chunk_by_type("IHDR").ok_or(PngError::InvalidImageHeader)?)?;
+    let opaque = !header.has_alpha() && png.chunk_by_type("tRNS").is_none();
+    let frames = apng::frames(png)?;
+    let mut slots: Vec<FrameSlots> = Vec::new();
+    for (n, frame) in frames.iter().enumerate() {
+        let fctl = frame.control();
+        // the frame shown after this one; playback loops back to frame 0
+        let next = frames[(n + 1) % frames.len()].control();
+        let covers_canvas = next.width() == header.width() && next.height() == header.height();
+        slots.push(FrameSlots {
+            frame      : n,
+            chunk_index: frame.chunks()[0],
+            delay_bits : delay_bits(fctl),
+            // frame 0 is blended onto a fully transparent canvas
+            blend      : n == 0 || opaque,
+            dispose    : opaque && covers_canvas && frames.len() > 1,
+        });
+    }
+    return Ok(slots);
+}
+
+pub fn capacity_bits(png: &Png) -> Result<usize> {
+    return Ok(slots(png)?.iter().map(|s| s.bits()).sum());
+}
+
+pub fn capacity(png: &Png) -> Result<usize> {
+    return Ok(capacity_bits(png)?.saturating_sub(LENGTH_BITS) / 8);
+}
+
+pub fn embed(png: &mut Png, data: &[u8]) -> Result<()> {
+    let slots = slots(png)?;
+    let available: usize = slots.iter().map(|s| s.bits()).sum::<usize>().saturating_sub(LENGTH_BITS) / 8;
+    if data.len() > available || data.len() > u16::MAX as usize {
+        return Err(Box::from(PngError::InsufficientCapacity(data.len(), available)));
+    }
+    let mut message: Vec<u8> = (data.len() as u16).to_be_bytes().to_vec();
+    message.extend_from_slice(data);
+    let mut bits = message.iter().flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1));
+    for slot in &slots {
+        let mut fctl = FrameControl::try_from(&png.chunks()[slot.chunk_index])?;
+        let mut scale: u16 = 0;
+        for _ in 0..slot.delay_bits {
+            scale = (scale << 1) | bits.next().unwrap_or(0) as u16;
+        }
+        let (num, den) = reduced_delay(&fctl);
+        fctl.set_delay(num * (scale + 1), den * (scale + 1));
+        if slot.blend {
+            fctl.set_blend_op(bits.next().unwrap_or(0));
+        }
+        if slot.dispose {
+            fctl.set_dispose_op(bits.next().unwrap_or(0));
+        }
+        png.set_chunk(slot.chunk_index, fctl.to_chunk());
+    }
+    return Ok(());
+}
+
+// Resets every hiding place to its plain value.
+pub fn clear(png: &mut Png) -> Result<()> {
+    return embed(png, &[]);
+}
+
+pub fn extract(png: &Png) -> Result<Vec<u8>> {
+    let mut bits: Vec<u8> = Vec::new();
+    for slot in slots(png)? {
+        let fctl = FrameControl::try_from(&png.chunks()[slot.chunk_index])?;
+        let (_, den) = reduced_delay(&fctl);
+        let scale = effective_den(&fctl) / den - 1;
+        for i in (0..slot.delay_bits).rev() {
+            bits.push(((scale >> i) & 1) as u8);
+        }
+        if slot.blend {
+            bits.push(fctl.blend_op() & 1);
+        }
+        if slot.dispose {
+            bits.push(fctl.dispose_op() & 1);
+        }
+    }
+    let bytes: Vec<u8> = bits.chunks_exact(8)
+                             .map(|b| b.iter().fold(0u8, |acc, bit| (acc << 1) | bit))
+                             .collect();
+    if bytes.len() < 2 {
+        return Err(Box::from(PngError::InsufficientCapacity(0, 0)));
+    }
+    let length = u16::from_be_bytes([bytes[0], bytes[1]]) as usize;
+    if length > bytes.len() - 2 {
+        return Err(Box::from(PngError::InvalidPayload));
+    }
+    return Ok(bytes[2..2 + length].to_vec());
+}
+
+// A denominator of 0 means 1/100 s per the APNG spec.
+fn effective_den(fctl: &FrameControl) -> u16 {
+    let (_, den) = fctl.delay();
+    return if den == 0 { 100 } else { den };
+}
+
+fn reduced_delay(fctl: &FrameControl) -> (u16, u16) {
+    let (num, _) = fctl.delay();
+    let den = effective_den(fctl);
+    let g = gcd(num, den);
+    return (num / g, den / g);
+}
+
+fn delay_bits(fctl: &FrameControl) -> u32 {
+    let (num, den) = reduced_delay(fctl);
+    let max_scale = u16::MAX / num.max(den);
+    return max_scale.ilog2().min(MAX_DELAY_BITS);
+}
+
+fn gcd(a: u16, b: u16) -> u16 {
+    return if b == 0 { a } else { gcd(b, a % b) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+
+    fn chunk(chunk_type: &str, data: Vec<u8>) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+    }
+
+    fn fctl(seq: u32, num: u16, den: u16) -> Chunk {
+        let mut data: Vec<u8> = Vec::new();
+        for v in [seq, 4, 4, 0, 0] {
+            data.extend_from_slice(&v.to_be_bytes());
+        }
+        data.extend_from_slice(&num.to_be_bytes());
+        data.extend_from_slice(&den.to_be_bytes());
+        data.extend_from_slice(&[0, 0]);
+        chunk("fcTL", data)
+    }
+
+    fn testing_apng(frames: u32, color_type: u8) -> Png {
+        let mut ihdr = 4u32.to_be_bytes().to_vec();
+        ihdr.extend_from_slice(&4u32.to_be_bytes());
+        ihdr.extend_from_slice(&[8, color_type, 0, 0, 0]);
+        let mut actl = frames.to_be_bytes().to_vec();
+        actl.extend_from_slice(&0u32.to_be_bytes());
+        let mut chunks = vec![chunk("IHDR", ihdr), chunk("acTL", actl)];
+        let mut seq = 0;
+        for n in 0..frames {
+            chunks.push(fctl(seq, 1, 10));
+            seq += 1;
+            if n == 0 {
+                chunks.push(chunk("IDAT", vec![1, 2, 3]));
+            } else {
+                let mut data = seq.to_be_bytes().to_vec();
+                data.extend_from_slice(&[1, 2, 3]);
+                chunks.push(chunk("fdAT", data));
+                seq += 1;
+            }
+        }
+        chunks.push(chunk("IEND", vec![]));
+        Png::from_chunks(chunks)
+    }
+
+    #[test]
+    fn test_slots() {
+        let opaque = slots(&testing_apng(3, 2)).unwrap();
+        assert!(opaque.iter().all(|s| s.delay_bits() == 3 && s.blend() && s.dispose()));
+        let alpha = slots(&testing_apng(3, 6)).unwrap();
+        assert!(alpha[0].blend() && !alpha[1].blend());
+        assert!(alpha.iter().all(|s| !s.dispose()));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut png = testing_apng(20, 2);
+        assert_eq!(capacity(&png).unwrap(), 10);
+        embed(&mut png, b"hidden").unwrap();
+        assert!(apng::validate(&png).is_ok());
+        for frame in apng::frames(&png).unwrap() {
+            let (num, den) = frame.control().delay();
+            assert_eq!(num as u32 * 10, den as u32);
+        }
+        assert_eq!(extract(&png).unwrap(), b"hidden");
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut png = testing_apng(20, 2);
+        let plain = png.as_bytes();
+        embed(&mut png, b"hidden").unwrap();
+        clear(&mut png).unwrap();
+        assert_eq!(png.as_bytes(), plain);
+    }
+
+    #[test]
+    fn test_too_large() {
+        let mut png = testing_apng(2, 6);
+        assert!(embed(&mut png, b"far too long for two frames").is_err());
+    }
+
+    #[test]
+    fn test_not_animated() {
+        let png = Png::from_chunks(vec![chunk("IEND", vec![])]);
+        assert!(slots(&png).is_err());
+    }
+}
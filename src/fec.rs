@@ -0,0 +1,332 @@
+//
+// author: xigang zhao
+//
+
+// Reed-Solomon forward error correction over GF(2^8) for payloads that may
+// get damaged on the way. The payload is split into equally sized blocks
+// that each get `parity` check bytes, and the codewords are interleaved byte
+// by byte, so a burst of damaged bytes is spread over all blocks:
+//
+//     header codeword (15) | codeword 0 byte 0 | codeword 1 byte 0 | ...
+//
+// The header holds the parity count and payload length and has its own
+// fixed amount of parity. Each block can repair parity / 2 damaged bytes.
+
+use std::sync::OnceLock;
+use crate::fec_error::FecError;
+use crate::Result;
+
+const BLOCK_SIZE: usize = 255;
+const HEADER_SIZE: usize = 5;
+const HEADER_PARITY: usize = 10;
+pub const MIN_PARITY: u8 = 2;
+pub const MAX_PARITY: u8 = 128;
+
+struct Tables {
+    exp: [u8; 512],
+    log: [u8; 256],
+}
+
+// exp/log tables for the field with the primitive polynomial x^8+x^4+x^3+x^2+1
+fn tables() -> &'static Tables {
+    static TABLES: OnceLock<Tables> = OnceLock::new();
+    return TABLES.get_or_init(|| {
+        let mut tables = Tables { exp: [0; 512], log: [0; 256] };
+        let mut x: u16 = 1;
+        for i in 0..255 {
+            tables.exp[i] = x as u8;
+            tables.log[x as usize] = i as u8;
+            x <<= 1;
+            if x & 0x100 != 0 {
+                x ^= 0x11d;
+            }
+        }
+        for i in 255..512 {
+            tables.exp[i] = tables.exp[i - 255];
+        }
+        tables
+    });
+}
+
+pub fn mul(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+    let t = tables();
+    return t.exp[t.log[a as usize] as usize + t.log[b as usize] as usize];
+}
+
+pub fn div(a: u8, b: u8) -> u8 {
+    if a == 0 {
+        return 0;
+    }
+    let t = tables();
+    return t.exp[t.log[a as usize] as usize + 255 - t.log[b as usize] as usize];
+}
+
+// alpha^power, for any integer power
+fn alpha(power: i64) -> u8 {
+    return tables().exp[power.rem_euclid(255) as usize];
+}
+
+// Evaluates a polynomial stored lowest coefficient first.
+fn eval_low(poly: &[u8], x: u8) -> u8 {
+    return poly.iter().rev().fold(0, |acc, &c| mul(acc, x) ^ c);
+}
+
+// Evaluates a codeword, whose first byte is the highest coefficient.
+fn eval_high(poly: &[u8], x: u8) -> u8 {
+    return poly.iter().fold(0, |acc, &c| mul(acc, x) ^ c);
+}
+
+// (x - a^0)(x - a^1)...(x - a^(parity-1)), highest coefficient first
+fn generator(parity: usize) -> Vec<u8> {
+    let mut g = vec![1u8];
+    for i in 0..parity {
+        let root = alpha(i as i64);
+        let mut next = g.clone();
+        next.push(0);
+        for j in 1..next.len() {
+            next[j] ^= mul(g[j - 1], root);
+        }
+        g = next;
+    }
+    return g;
+}
+
+// Appends `parity` check bytes to `data`.
+fn encode_block(data: &[u8], parity: usize) -> Vec<u8> {
+    let g = generator(parity);
+    let mut buf = data.to_vec();
+    buf.resize(data.len() + parity, 0);
+    for i in 0..data.len() {
+        let coef = buf[i];
+        if coef != 0 {
+            for j in 1..g.len() {
+                buf[i + j] ^= mul(g[j], coef);
+            }
+        }
+    }
+    buf[..data.len()].copy_from_slice(data);
+    return buf;
+}
+
+// Corrects `codeword` in place and returns the number of bytes repaired.
+fn decode_block(codeword: &mut [u8], parity: usize) -> Option<usize> {
+    let n = codeword.len();
+    let syndromes: Vec<u8> = (0..parity).map(|i| eval_high(codeword, alpha(i as i64))).collect();
+    if syndromes.iter().all(|&s| s == 0) {
+        return Some(0);
+    }
+
+    // Berlekamp-Massey: the error locator, lowest coefficient first
+    let mut locator = vec![1u8];
+    let mut previous = vec![1u8];
+    let mut errors = 0;
+    let mut shift = 1;
+    let mut last_discrepancy = 1u8;
+    for k in 0..parity {
+        let mut d = syndromes[k];
+        for i in 1..=errors.min(locator.len() - 1) {
+            d ^= mul(locator[i], syndromes[k - i]);
+        }
+        if d == 0 {
+            shift += 1;
+            continue;
+        }
+        let scale = div(d, last_discrepancy);
+        let mut next = locator.clone();
+        next.resize(next.len().max(previous.len() + shift), 0);
+        for (i, &b) in previous.iter().enumerate() {
+            next[i + shift] ^= mul(scale, b);
+        }
+        if 2 * errors <= k {
+            previous = locator;
+            errors = k + 1 - errors;
+            last_discrepancy = d;
+            shift = 1;
+        } else {
+            shift += 1;
+        }
+        locator = next;
+    }
+    while locator.len() > 1 && locator[locator.len() - 1] == 0 {
+        locator.pop();
+    }
+    if 2 * errors > parity || locator.len() - 1 != errors {
+        return None;
+    }
+
+    // Chien search: byte j is the coefficient of x^(n-1-j), X = a^(n-1-j)
+    let positions: Vec<usize> = (0..n).filter(|&j| eval_low(&locator, alpha(-((n - 1 - j) as i64))) == 0).collect();
+    if positions.len() != errors {
+        return None;
+    }
+
+    // Forney: e = X * omega(1/X) / locator'(1/X)
+    let mut omega = vec![0u8; parity];
+    for (i, &s) in syndromes.iter().enumerate() {
+        for (j, &l) in locator.iter().enumerate() {
+            if i + j < parity {
+                omega[i + j] ^= mul(s, l);
+            }
+        }
+    }
+    let derivative: Vec<u8> = locator.iter().enumerate().skip(1)
+                                     .map(|(i, &c)| if i % 2 == 1 { c } else { 0 })
+                                     .collect();
+    for &j in &positions {
+        let x = alpha((n - 1 - j) as i64);
+        let x_inv = div(1, x);
+        let denominator = eval_low(&derivative, x_inv);
+        if denominator == 0 {
+            return None;
+        }
+        codeword[j] ^= mul(x, div(eval_low(&omega, x_inv), denominator));
+    }
+    if (0..parity).any(|i| eval_high(codeword, alpha(i as i64)) != 0) {
+        return None;
+    }
+    return Some(errors);
+}
+
+// Number of blocks and data bytes per block for a payload.
+fn layout(length: usize, parity: usize) -> (usize, usize) {
+    if length == 0 {
+        return (0, 0);
+    }
+    let blocks = length.div_ceil(BLOCK_SIZE - parity);
+    return (blocks, length.div_ceil(blocks));
+}
+
+// Size of the encoded form of a payload of `length` bytes.
+pub fn encoded_size(length: usize, parity: u8) -> usize {
+    let (blocks, size) = layout(length, parity as usize);
+    return HEADER_SIZE + HEADER_PARITY + blocks * (size + parity as usize);
+}
+
+pub fn encode(data: &[u8], parity: u8) -> Result<Vec<u8>> {
+    if !(MIN_PARITY..=MAX_PARITY).contains(&parity) {
+        return Err(Box::from(FecError::InvalidParity(parity)));
+    }
+    let parity = parity as usize;
+    let mut header = vec![parity as u8];
+    header.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let mut encoded = encode_block(&header, HEADER_PARITY);
+
+    let (blocks, size) = layout(data.len(), parity);
+    let codewords: Vec<Vec<u8>> = (0..blocks).map(|b| {
+        let mut block = data[(b * size).min(data.len())..((b + 1) * size).min(data.len())].to_vec();
+        block.resize(size, 0);
+        encode_block(&block, parity)
+    }).collect();
+    for i in 0..size + parity {
+        encoded.extend(codewords.iter().map(|cw| cw[i]));
+    }
+    return Ok(encoded);
+}
+
+// Returns the payload and the number of bytes that were corrected.
+pub fn decode(encoded: &[u8]) -> Result<(Vec<u8>, usize)> {
+    if encoded.len() < HEADER_SIZE + HEADER_PARITY {
+        return Err(Box::from(FecError::InvalidHeader));
+    }
+    let mut header = encoded[..HEADER_SIZE + HEADER_PARITY].to_vec();
+    let mut corrected = decode_block(&mut header, HEADER_PARITY).ok_or(FecError::InvalidHeader)?;
+    let parity = header[0];
+    if !(MIN_PARITY..=MAX_PARITY).contains(&parity) {
+        return Err(Box::from(FecError::InvalidParity(parity)));
+    }
+    let parity = parity as usize;
+    let length = u32::from_be_bytes(header[1..HEADER_SIZE].try_into().unwrap()) as usize;
+
+    let (blocks, size) = layout(length, parity);
+    let body = &encoded[HEADER_SIZE + HEADER_PARITY..];
+    let expected = blocks * (size + parity);
+    if body.len() < expected {
+        return Err(Box::from(FecError::Truncated(body.len(), expected)));
+    }
+    let mut data: Vec<u8> = Vec::with_capacity(blocks * size);
+    for b in 0..blocks {
+        let mut codeword: Vec<u8> = body[..expected].iter().skip(b).step_by(blocks).copied().collect();
+        corrected += decode_block(&mut codeword, parity).ok_or(FecError::Uncorrectable(b))?;
+        data.extend_from_slice(&codeword[..size]);
+    }
+    data.truncate(length);
+    return Ok((data, corrected));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn test_field() {
+        assert_eq!(mul(3, 7), 9);
+        for a in 1..=255u8 {
+            assert_eq!(mul(a, div(1, a)), 1);
+        }
+    }
+
+    #[test]
+    fn test_block_random_errors() {
+        let mut rng = StdRng::seed_from_u64(38);
+        for parity in [2, 10, 32] {
+            for errors in 0..=parity / 2 {
+                let data: Vec<u8> = (0..100).map(|_| rng.random()).collect();
+                let codeword = encode_block(&data, parity);
+                let mut damaged = codeword.clone();
+                let mut positions: Vec<usize> = Vec::new();
+                while positions.len() < errors {
+                    let p = rng.random_range(0..damaged.len());
+                    if !positions.contains(&p) {
+                        positions.push(p);
+                        damaged[p] ^= rng.random_range(1..=255u8);
+                    }
+                }
+                assert_eq!(decode_block(&mut damaged, parity), Some(errors));
+                assert_eq!(damaged, codeword);
+            }
+        }
+    }
+
+    #[test]
+    fn test_too_many_errors() {
+        let codeword = encode_block(b"hello world", 4);
+        let mut damaged = codeword.clone();
+        for b in damaged.iter_mut().take(5) {
+            *b ^= 0xff;
+        }
+        assert_ne!(decode_block(&mut damaged, 4).map(|_| damaged.clone()), Some(codeword));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for length in [0, 1, 200, 1000] {
+            let data: Vec<u8> = (0..length).map(|i| (i * 31 % 256) as u8).collect();
+            let encoded = encode(&data, 16).unwrap();
+            assert_eq!(decode(&encoded).unwrap(), (data, 0));
+        }
+    }
+
+    #[test]
+    fn test_burst() {
+        // 4 blocks of 8 parity bytes repair a burst of 16 consecutive bytes
+        let data: Vec<u8> = (0..900).map(|i| (i % 251) as u8).collect();
+        let mut encoded = encode(&data, 8).unwrap();
+        for b in &mut encoded[100..116] {
+            *b = !*b;
+        }
+        assert_eq!(decode(&encoded).unwrap(), (data, 16));
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(encode(b"x", 1).is_err());
+        let mut encoded = encode(b"hello", 4).unwrap();
+        encoded.pop();
+        assert!(decode(&encoded).is_err());
+    }
+}
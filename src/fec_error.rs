@@ -0,0 +1,26 @@
+//
+// author: xigang zhao
+//
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum FecError {
+    InvalidParity(u8),
+    InvalidHeader,
+    Truncated(usize, usize),
+    Uncorrectable(usize),
+}
+
+impl fmt::Display for FecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FecError::InvalidParity(n)           => write!(f, "Invalid number of parity bytes: {}", n),
+            FecError::InvalidHeader              => write!(f, "Error correction header is damaged beyond repair"),
+            FecError::Truncated(found, expected) => write!(f, "Error correction data is {} bytes, expected {}", found, expected),
+            FecError::Uncorrectable(block)       => write!(f, "Block {} has more errors than can be corrected", block),
+        }
+    }
+}
+
+impl std::error::Error for FecError {}
@@ -0,0 +1,276 @@
+//
+// author: xigang zhao
+//
+
+// Per-recipient fingerprints for tracing leaked copies. The recipient ID is
+// sealed into a fixed-size mark under keys derived from a secret only the
+// sender knows:
+//
+//     nonce (12) | ChaCha20-Poly1305 of [length (1) | ID | zeros] (32 + 16)
+//
+// The nonce is derived from the ID, so a recipient always gets the same mark
+// and nobody without the secret can read, forge or even compare IDs. The mark
+// is stored twice: in a private fpRt chunk, and over and over in the least
+// significant bit of every sample, XORed with a keyed stream so the bits look
+// like noise. The pixel copy survives stripping metadata and lossless
+// re-encoding; trace takes a majority vote over all its copies.
+
+use hkdf::Hkdf;
+use sha2::{Digest, Sha256};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use std::str::FromStr;
+use zeroize::Zeroizing;
+use crate::apng;
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::fingerprint_error::FingerprintError;
+use crate::pixels::Pixels;
+use crate::png::Png;
+use crate::Result;
+
+pub const FINGERPRINT_CHUNK: &str = "fpRt";
+pub const MAX_ID_LEN: usize = 31;
+
+const NONCE_SIZE: usize = 12;
+const MARK_SIZE: usize = NONCE_SIZE + 1 + MAX_ID_LEN + 16;
+const MARK_BITS: usize = 8 * MARK_SIZE;
+// fewer copies than this and a few flipped bits lose the mark
+const MIN_COPIES: usize = 3;
+
+pub struct FingerprintKey {
+    mark  : Zeroizing<[u8; 32]>,
+    nonce : Zeroizing<[u8; 32]>,
+    stream: Zeroizing<[u8; 32]>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PixelTrace {
+    id       : String,
+    copies   : usize,
+    agreement: f64,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Trace {
+    chunk : Option<String>,
+    pixels: Option<PixelTrace>,
+}
+
+impl FingerprintKey {
+    pub fn derive(secret: &str) -> FingerprintKey {
+        let hkdf = Hkdf::<Sha256>::new(None, secret.as_bytes());
+        let expand = |info: &[u8]| {
+            let mut key = Zeroizing::new([0u8; 32]);
+            hkdf.expand(info, key.as_mut()).unwrap();
+            key
+        };
+        return FingerprintKey {
+            mark  : expand(b"deepng fingerprint mark"),
+            nonce : expand(b"deepng fingerprint nonce"),
+            stream: expand(b"deepng fingerprint stream"),
+        };
+    }
+
+    // One keystream bit for every sample, so the marked bits look random.
+    fn stream_bits(&self, count: usize) -> Vec<u8> {
+        let mut bits: Vec<u8> = Vec::with_capacity(count + 255);
+        let mut counter: u64 = 0;
+        while bits.len() < count {
+            let block = Sha256::new().chain_update(self.stream.as_ref())
+                                     .chain_update(counter.to_be_bytes())
+                                     .finalize();
+            bits.extend(block.iter().flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1)));
+            counter += 1;
+        }
+        bits.truncate(count);
+        return bits;
+    }
+}
+
+impl PixelTrace {
+    pub fn id(&self) -> &str {
+        return &self.id;
+    }
+
+    pub fn copies(&self) -> usize {
+        return self.copies;
+    }
+
+    // The share of marked samples that agree with the recovered mark.
+    pub fn agreement(&self) -> f64 {
+        return self.agreement;
+    }
+}
+
+impl Trace {
+    pub fn chunk(&self) -> Option<&str> {
+        return self.chunk.as_deref();
+    }
+
+    pub fn pixels(&self) -> Option<&PixelTrace> {
+        return self.pixels.as_ref();
+    }
+}
+
+pub fn seal_mark(id: &str, key: &FingerprintKey) -> Result<Vec<u8>> {
+    if id.is_empty() {
+        return Err(Box::from(FingerprintError::EmptyId));
+    }
+    if id.len() > MAX_ID_LEN {
+        return Err(Box::from(FingerprintError::IdTooLong(id.len())));
+    }
+    let mut nonce = [0u8; NONCE_SIZE];
+    Hkdf::<Sha256>::new(Some(key.nonce.as_ref()), id.as_bytes())
+                   .expand(b"nonce", &mut nonce)
+                   .unwrap();
+    let mut plaintext = vec![id.len() as u8];
+    plaintext.extend_from_slice(id.as_bytes());
+    plaintext.resize(1 + MAX_ID_LEN, 0);
+    let ciphertext = ChaCha20Poly1305::new(Key::from_slice(key.mark.as_ref()))
+                         .encrypt(Nonce::from_slice(&nonce), plaintext.as_slice())
+                         .unwrap();
+    let mut mark = nonce.to_vec();
+    mark.extend_from_slice(&ciphertext);
+    return Ok(mark);
+}
+
+// Returns the recipient ID of a mark made with `key`.
+pub fn open_mark(mark: &[u8], key: &FingerprintKey) -> Option<String> {
+    if mark.len() != MARK_SIZE {
+        return None;
+    }
+    let (nonce, ciphertext) = mark.split_at(NONCE_SIZE);
+    let plaintext = ChaCha20Poly1305::new(Key::from_slice(key.mark.as_ref()))
+                        .decrypt(Nonce::from_slice(nonce), ciphertext)
+                        .ok()?;
+    let id = plaintext.get(1..1 + plaintext[0] as usize)?;
+    return String::from_utf8(id.to_vec()).ok();
+}
+
+// Marks `png` for the recipient `id`, replacing any earlier fingerprint.
+// Returns how many copies of the mark went into the pixels, or None when the
+// image has no room for them: palette images, bit depths below 8 and images
+// with fewer than a few copies' worth of samples.
+pub fn fingerprint(png: &mut Png, id: &str, key: &FingerprintKey) -> Result<Option<usize>> {
+    let mark = seal_mark(id, key)?;
+    while png.remove_first_chunk(FINGERPRINT_CHUNK).is_ok() {}
+    let chunk = Chunk::new(ChunkType::from_str(FINGERPRINT_CHUNK).unwrap(), mark.clone());
+    png.insert_chunk(apng::hidden_chunk_position(png), chunk);
+    let mut pixels = Pixels::decode(png)?;
+    let offsets = usable_offsets(&pixels);
+    if offsets.is_empty() {
+        return Ok(None);
+    }
+    let stream = key.stream_bits(offsets.len());
+    for (j, &offset) in offsets.iter().enumerate() {
+        let i = j % MARK_BITS;
+        let bit = (mark[i / 8] >> (7 - i % 8)) & 1;
+        let byte = &mut pixels.data_mut()[offset];
+        *byte = (*byte & !1) | (bit ^ stream[j]);
+    }
+    pixels.encode(png)?;
+    return Ok(Some(offsets.len() / MARK_BITS));
+}
+
+// Looks for a fingerprint made with `key` in the chunk and in the pixels.
+pub fn trace(png: &Png, key: &FingerprintKey) -> Result<Trace> {
+    let chunk = png.chunk_by_type(FINGERPRINT_CHUNK).and_then(|ck| open_mark(ck.data(), key));
+    let pixels = Pixels::decode(png)?;
+    let offsets = usable_offsets(&pixels);
+    if offsets.is_empty() {
+        return Ok(Trace { chunk, pixels: None });
+    }
+    let stream = key.stream_bits(offsets.len());
+    let mut votes = vec![0usize; MARK_BITS];
+    for (j, &offset) in offsets.iter().enumerate() {
+        votes[j % MARK_BITS] += ((pixels.data()[offset] & 1) ^ stream[j]) as usize;
+    }
+    let copies = offsets.len() / MARK_BITS;
+    let mut mark = vec![0u8; MARK_SIZE];
+    let mut agreeing = 0;
+    for (i, &ones) in votes.iter().enumerate() {
+        // bits past the last full copy get one vote less
+        let total = copies + (i < offsets.len() % MARK_BITS) as usize;
+        if 2 * ones > total {
+            mark[i / 8] |= 1 << (7 - i % 8);
+        }
+        agreeing += ones.max(total - ones);
+    }
+    let pixels = open_mark(&mark, key).map(|id| PixelTrace {
+        id,
+        copies,
+        agreement: agreeing as f64 / offsets.len() as f64,
+    });
+    return Ok(Trace { chunk, pixels });
+}
+
+fn usable_offsets(pixels: &Pixels) -> Vec<usize> {
+    if pixels.header().color_type() == 3 {
+        return Vec::new();
+    }
+    let offsets = pixels.sample_offsets();
+    if offsets.len() < MIN_COPIES * MARK_BITS {
+        return Vec::new();
+    }
+    return offsets;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixels::tests::testing_png;
+
+    #[test]
+    fn test_mark() {
+        let key = FingerprintKey::derive("sender secret");
+        let mark = seal_mark("partner-acme", &key).unwrap();
+        assert_eq!(mark.len(), MARK_SIZE);
+        assert_eq!(seal_mark("partner-acme", &key).unwrap(), mark);
+        assert_ne!(seal_mark("partner-bolt", &key).unwrap()[..NONCE_SIZE], mark[..NONCE_SIZE]);
+        assert_eq!(open_mark(&mark, &key).as_deref(), Some("partner-acme"));
+        assert_eq!(open_mark(&mark, &FingerprintKey::derive("other secret")), None);
+        assert!(seal_mark("", &key).is_err());
+        assert!(seal_mark(&"x".repeat(MAX_ID_LEN + 1), &key).is_err());
+    }
+
+    #[test]
+    fn test_trace() {
+        let key = FingerprintKey::derive("sender secret");
+        let mut png = testing_png(40, 30, 2, 0);
+        assert_eq!(trace(&png, &key).unwrap().pixels(), None);
+        let copies = fingerprint(&mut png, "partner-acme", &key).unwrap();
+        assert_eq!(copies, Some(40 * 30 * 3 / MARK_BITS));
+        let found = trace(&png, &key).unwrap();
+        assert_eq!(found.chunk(), Some("partner-acme"));
+        assert_eq!(found.pixels().unwrap().id(), "partner-acme");
+        assert_eq!(found.pixels().unwrap().agreement(), 1.0);
+        assert_eq!(trace(&png, &FingerprintKey::derive("other secret")).unwrap(), Trace::default());
+    }
+
+    #[test]
+    fn test_trace_stripped_and_damaged() {
+        let key = FingerprintKey::derive("sender secret");
+        let mut png = testing_png(40, 30, 6, 0);
+        fingerprint(&mut png, "partner-acme", &key).unwrap();
+        png.remove_first_chunk(FINGERPRINT_CHUNK).unwrap();
+        // flip every seventh low bit
+        let mut pixels = Pixels::decode(&png).unwrap();
+        for offset in pixels.sample_offsets().into_iter().step_by(7) {
+            pixels.data_mut()[offset] ^= 1;
+        }
+        pixels.encode(&mut png).unwrap();
+        let found = trace(&png, &key).unwrap();
+        assert_eq!(found.chunk(), None);
+        assert_eq!(found.pixels().unwrap().id(), "partner-acme");
+        assert!(found.pixels().unwrap().agreement() < 0.9);
+    }
+
+    #[test]
+    fn test_too_small() {
+        let key = FingerprintKey::derive("sender secret");
+        let mut png = testing_png(8, 8, 0, 0);
+        assert_eq!(fingerprint(&mut png, "partner-acme", &key).unwrap(), None);
+        assert_eq!(trace(&png, &key).unwrap().chunk(), Some("partner-acme"));
+    }
+}
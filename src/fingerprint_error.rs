@@ -0,0 +1,24 @@
+//
+// author: xigang zhao
+//
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum FingerprintError {
+    EmptyId,
+    IdTooLong(usize),
+    NotFound,
+}
+
+impl fmt::Display for FingerprintError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FingerprintError::EmptyId      => write!(f, "The recipient ID is empty"),
+            FingerprintError::IdTooLong(n) => write!(f, "The recipient ID is {} bytes, at most 31 fit in a fingerprint", n),
+            FingerprintError::NotFound     => write!(f, "No fingerprint made with this secret was found"),
+        }
+    }
+}
+
+impl std::error::Error for FingerprintError {}
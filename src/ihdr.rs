@@ -0,0 +1,141 @@
+//
+// author: xigang zhao
+//
+
+use std::convert::TryFrom;
+use crate::chunk::Chunk;
+use crate::png_error::PngError;
+
+// the PNG specification limits both dimensions to 2^31 - 1
+const MAX_DIMENSION: u32 = (1 << 31) - 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageHeader {
+    width      : u32,
+    height     : u32,
+    bit_depth  : u8,
+    color_type : u8,
+    compression: u8,
+    filter     : u8,
+    interlace  : u8,
+}
+
+impl TryFrom<&Chunk> for ImageHeader {
+    type Error = PngError;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        if &chunk.chunk_type().bytes() != b"IHDR" || chunk.data().len() != ImageHeader::SIZE {
+            return Err(PngError::InvalidImageHeader);
+        }
+        let data = chunk.data();
+        let header = ImageHeader {
+            width      : u32::from_be_bytes(data[0..4].try_into().unwrap()),
+            height     : u32::from_be_bytes(data[4..8].try_into().unwrap()),
+            bit_depth  : data[8],
+            color_type : data[9],
+            compression: data[10],
+            filter     : data[11],
+            interlace  : data[12],
+        };
+        let depth_ok = match header.color_type {
+            0 => [1, 2, 4, 8, 16].contains(&header.bit_depth),
+            3 => [1, 2, 4, 8].contains(&header.bit_depth),
+            2 | 4 | 6 => [8, 16].contains(&header.bit_depth),
+            _ => false,
+        };
+        if !size_ok(header.width) || !size_ok(header.height) || !depth_ok
+            || header.compression != 0 || header.filter != 0 || header.interlace > 1 {
+            return Err(PngError::InvalidImageHeader);
+        }
+        return Ok(header);
+    }
+}
+
+impl ImageHeader {
+    pub const SIZE: usize = 13;
+
+    // The same format at another size, as an APNG frame has.
+    pub fn with_size(&self, width: u32, height: u32) -> Result<ImageHeader, PngError> {
+        if !size_ok(width) || !size_ok(height) {
+            return Err(PngError::InvalidImageHeader);
+        }
+        return Ok(ImageHeader { width, height, ..*self });
+    }
+
+    pub fn width(&self) -> u32 {
+        return self.width;
+    }
+
+    pub fn height(&self) -> u32 {
+        return self.height;
+    }
+
+    pub fn bit_depth(&self) -> u8 {
+        return self.bit_depth;
+    }
+
+    pub fn color_type(&self) -> u8 {
+        return self.color_type;
+    }
+
+    pub fn interlace(&self) -> u8 {
+        return self.interlace;
+    }
+
+    pub fn channels(&self) -> usize {
+        match self.color_type {
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => 1,
+        }
+    }
+
+    pub fn has_alpha(&self) -> bool {
+        return self.color_type == 4 || self.color_type == 6;
+    }
+
+    pub fn bits_per_pixel(&self) -> usize {
+        return self.channels() * self.bit_depth as usize;
+    }
+}
+
+fn size_ok(dimension: u32) -> bool {
+    return (1..=MAX_DIMENSION).contains(&dimension);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::chunk_type::ChunkType;
+
+    fn ihdr_chunk(width: u32, height: u32, depth: u8, color: u8) -> Chunk {
+        let mut data: Vec<u8> = width.to_be_bytes().to_vec();
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&[depth, color, 0, 0, 0]);
+        Chunk::new(ChunkType::from_str("IHDR").unwrap(), data)
+    }
+
+    #[test]
+    fn test_valid_header() {
+        let header = ImageHeader::try_from(&ihdr_chunk(64, 48, 8, 6)).unwrap();
+        assert_eq!(header.width(), 64);
+        assert_eq!(header.height(), 48);
+        assert_eq!(header.channels(), 4);
+        assert_eq!(header.bits_per_pixel(), 32);
+        assert!(header.has_alpha());
+    }
+
+    #[test]
+    fn test_invalid_depth() {
+        assert!(ImageHeader::try_from(&ihdr_chunk(64, 48, 4, 2)).is_err());
+        assert!(ImageHeader::try_from(&ihdr_chunk(0, 48, 8, 2)).is_err());
+    }
+
+    #[test]
+    fn test_dimensions() {
+        assert!(ImageHeader::try_from(&ihdr_chunk(0x7fff_ffff, 1, 8, 2)).is_ok());
+        assert!(ImageHeader::try_from(&ihdr_chunk(0x8000_0000, 1, 8, 2)).is_err());
+        assert!(ImageHeader::try_from(&ihdr_chunk(1, u32::MAX, 8, 2)).is_err());
+    }
+}
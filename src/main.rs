@@ -1,22 +1,75 @@
+#![allow(clippy::needless_return, clippy::redundant_field_names, clippy::bool_comparison)]
+#![cfg_attr(test, allow(unused_imports, clippy::to_string_in_format_args))]
+
 mod chunk_type;
+// the original chunk and png modules keep API that only their tests use
+#[allow(dead_code)]
 mod chunk;
+#[allow(dead_code)]
 mod png;
 mod chunk_type_error;
+#[allow(dead_code)]
 mod chunk_error;
+#[allow(dead_code)]
 mod png_error;
-use clap::{arg, value_parser, Arg, ArgAction, ArgGroup, Command};
+mod text_chunk;
+mod cover_text;
+mod camouflage;
+mod ihdr;
+mod apng;
+mod apng_error;
+mod fctl_stego;
+mod check;
+mod zlib;
+mod pixels;
+mod scan;
+mod steganalysis;
+mod scrub;
+mod policy;
+mod policy_error;
+mod keys;
+mod signing;
+mod signing_error;
+mod content_hash;
+mod diff;
+mod fec;
+mod fec_error;
+mod container;
+mod container_error;
+mod discover;
+mod archive;
+mod archive_error;
+mod spread;
+mod spread_error;
+mod shamir;
+mod shamir_error;
+mod capacity;
+mod crypto;
+mod crypto_error;
+mod padding;
+mod padding_error;
+mod secret;
+mod secret_error;
+mod fingerprint;
+mod fingerprint_error;
+use clap::{arg, value_parser, Arg, ArgAction, ArgGroup, ArgMatches, Command};
 use std::{fs, io::Write, str::FromStr};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use text_chunk::{TextChunk, TextKind};
 
 pub type Error = Box<dyn std::error::Error>;
 pub type Result<T> = std::result::Result<T, Error>;
 
 fn main() -> Result<()> {
+    return run(&cli().get_matches());
+}
+
+fn cli() -> Command {
     let input = arg!(-f --file "The PNG file to encode into").required(true)
                                             .value_name("FILE")
                                             .action(ArgAction::Set)
                                             .value_parser(value_parser!(PathBuf));
-    let ck_type  = arg!(-t --type "The chunk type to use for encoding").required(true)
+    let ck_type  = arg!(-t --type "The chunk type to use for encoding")
                                             .value_name("TYPE")
                                             .action(ArgAction::Set)
                                             .value_parser(value_parser!(String));
@@ -33,99 +86,1172 @@ fn main() -> Result<()> {
                                             .value_name("FILE")
                                             .action(ArgAction::Set)
                                             .value_parser(value_parser!(PathBuf));
+    let method = arg!(--method "The embedding method: a private chunk, an ordinary text chunk, APNG frame controls or data after IEND")
+                                            .value_name("METHOD")
+                                            .action(ArgAction::Set)
+                                            .value_parser(["chunk", "text", "fctl", "trailer"])
+                                            .default_value("chunk");
+    let keyword = arg!(-k --keyword "The text keyword to store the message under")
+                                            .value_name("KEYWORD")
+                                            .action(ArgAction::Set)
+                                            .value_parser(value_parser!(String))
+                                            .default_value("Comment");
+    let compress = arg!(-z --compress "Store the text in a compressed zTXt chunk")
+                                            .action(ArgAction::SetTrue);
+    let key = arg!(--key "Derive the chunk type from a passphrase read from SOURCE instead of --type, like --password")
+                                            .value_name("SOURCE")
+                                            .num_args(0..=1)
+                                            .default_missing_value(secret::PROMPT)
+                                            .action(ArgAction::Set)
+                                            .value_parser(value_parser!(String))
+                                            .conflicts_with("type");
     let encode = Command::new("encode")
-                         .arg(input.clone())
+                         .arg(input.clone().required(false).required_unless_present("spread"))
                          .arg(ck_type.clone())
+                         .arg(key.clone())
                          .arg(msg_file.clone())
                          .arg(message.clone())
                          .arg(output.clone())
-                         .group(ArgGroup::new("messages").args(["msg_file", "message"])
+                         .arg(method.clone())
+                         .arg(keyword.clone())
+                         .arg(compress)
+                         .arg(arg!(--fec "Add Reed-Solomon error correction with PARITY check bytes per 255-byte block")
+                                  .value_name("PARITY")
+                                  .action(ArgAction::Set)
+                                  .value_parser(value_parser!(u8).range(fec::MIN_PARITY as i64..=fec::MAX_PARITY as i64)))
+                         .arg(arg!(--deflate "Compress the message before embedding it").action(ArgAction::SetTrue))
+                         .arg(arg!(--replace "Let the trailer method overwrite trailing data already in the file")
+                                  .action(ArgAction::SetTrue))
+                         .arg(arg!(--name "Store the message under this name, replacing any payload with the same name")
+                                  .value_name("NAME")
+                                  .action(ArgAction::Set)
+                                  .value_parser(value_parser!(String)))
+                         .arg(arg!(--password "Encrypt the message with a password from SOURCE: prompt (default), env:VAR, fd:N or file:PATH")
+                                  .value_name("SOURCE")
+                                  .num_args(0..=1)
+                                  .default_missing_value(secret::PROMPT)
+                                  .action(ArgAction::Set)
+                                  .value_parser(value_parser!(String)))
+                         .arg(arg!(--pad "Hide the size of the encrypted message: pow2, buckets:SIZES or random:MIN-MAX")
+                                  .value_name("SCHEME")
+                                  .action(ArgAction::Set)
+                                  .value_parser(padding::Padding::from_str)
+                                  .requires("encryption"))
+                         .arg(arg!(--recipient "Encrypt the message for the owner of this public key, given as a key or a key file")
+                                  .value_name("KEY")
+                                  .action(ArgAction::Append)
+                                  .value_parser(value_parser!(String)))
+                         .arg(arg!(--"decoy-message" "A harmless message that --decoy-password reveals instead")
+                                  .value_name("TEXT")
+                                  .action(ArgAction::Set)
+                                  .value_parser(value_parser!(String))
+                                  .requires("decoy-password"))
+                         .arg(arg!(--"decoy-file" "A file with a harmless payload that --decoy-password reveals instead")
+                                  .value_name("FILE")
+                                  .action(ArgAction::Set)
+                                  .value_parser(value_parser!(PathBuf))
+                                  .conflicts_with("decoy-message")
+                                  .requires("decoy-password"))
+                         .arg(arg!(--"decoy-password" "Read the password that reveals the decoy from SOURCE, like --password")
+                                  .value_name("SOURCE")
+                                  .num_args(0..=1)
+                                  .default_missing_value(secret::PROMPT)
+                                  .action(ArgAction::Set)
+                                  .value_parser(value_parser!(String))
+                                  .requires("password"))
+                         .arg(arg!(--dir "Pack this directory, with its permissions and modification times, into the payload")
+                                  .value_name("DIR")
+                                  .action(ArgAction::Set)
+                                  .value_parser(value_parser!(PathBuf)))
+                         .arg(arg!(--spread "Split the payload across these carrier files, which are updated in place")
+                                  .value_name("FILE")
+                                  .num_args(1..)
+                                  .action(ArgAction::Set)
+                                  .value_parser(value_parser!(PathBuf))
+                                  .conflicts_with_all(["file", "out"]))
+                         .arg(arg!(--shares "Split the payload into N secret shares, one per --spread carrier")
+                                  .value_name("N")
+                                  .action(ArgAction::Set)
+                                  .value_parser(value_parser!(u8).range(shamir::MIN_SHARES as i64..))
+                                  .requires_all(["spread", "threshold"]))
+                         .arg(arg!(--threshold "The number of shares needed to recover the payload")
+                                  .value_name("K")
+                                  .action(ArgAction::Set)
+                                  .value_parser(value_parser!(u8).range(shamir::MIN_SHARES as i64..))
+                                  .requires("shares"))
+                         .group(ArgGroup::new("messages").args(["msg_file", "message", "dir"])
                                                              .multiple(false)
-                                                             .required(true));
+                                                             .required(true))
+                         .group(ArgGroup::new("encryption").args(["password", "recipient"])
+                                                               .multiple(false));
     let decode = Command::new("decode")
-                         .arg(input)
+                         .arg(input.required(false).required_unless_present("spread"))
                          .arg(ck_type)
+                         .arg(key)
                          .arg(msg_file)
-                         .arg(output);
+                         .arg(output)
+                         .arg(method)
+                         .arg(keyword)
+                         .arg(arg!(--auto "Look for payloads everywhere instead of using --type and --method")
+                                  .action(ArgAction::SetTrue)
+                                  .conflicts_with_all(["type", "key", "method", "keyword"]))
+                         .arg(arg!(--pick "Extract the payload with this number from the --auto list")
+                                  .value_name("N")
+                                  .action(ArgAction::Set)
+                                  .value_parser(value_parser!(usize))
+                                  .requires("auto"))
+                         .arg(arg!(--name "Extract the payload stored under this name, wherever it is")
+                                  .value_name("NAME")
+                                  .action(ArgAction::Set)
+                                  .value_parser(value_parser!(String))
+                                  .conflicts_with_all(["type", "key", "method", "keyword", "auto"]))
+                         .arg(arg!(--password "Read the password of an encrypted payload from SOURCE: prompt (default), env:VAR, fd:N or file:PATH")
+                                  .value_name("SOURCE")
+                                  .num_args(0..=1)
+                                  .default_missing_value(secret::PROMPT)
+                                  .action(ArgAction::Set)
+                                  .value_parser(value_parser!(String)))
+                         .arg(arg!(--identity "The identity file of a recipient of an encrypted payload")
+                                  .value_name("FILE")
+                                  .action(ArgAction::Set)
+                                  .value_parser(value_parser!(PathBuf)))
+                         .arg(arg!(--"extract-to" "Unpack a directory payload into DIR")
+                                  .value_name("DIR")
+                                  .action(ArgAction::Set)
+                                  .value_parser(value_parser!(PathBuf))
+                                  .conflicts_with("msg_file"))
+                         .arg(arg!(--spread "Rebuild a payload from the fragments or shares in these files, given in any order")
+                                  .value_name("FILE")
+                                  .num_args(1..)
+                                  .action(ArgAction::Set)
+                                  .value_parser(value_parser!(PathBuf))
+                                  .conflicts_with_all(["file", "type", "key", "method", "keyword", "auto", "name", "out"]));
+    let list = Command::new("list")
+                       .about("List the chunks of a PNG file, grouped by frame for animated PNGs")
+                       .arg(arg!(-f --file "The PNG file to list").required(true)
+                                            .value_name("FILE")
+                                            .action(ArgAction::Set)
+                                            .value_parser(value_parser!(PathBuf)));
+    let policy = arg!(--policy "A policy file of allowed and denied chunk types")
+                                            .value_name("POLICY")
+                                            .action(ArgAction::Set)
+                                            .value_parser(value_parser!(PathBuf));
+    let check = Command::new("check")
+                        .about("Check the structure of a PNG file")
+                        .arg(arg!(-f --file "The PNG file to check").required(true)
+                                             .value_name("FILE")
+                                             .action(ArgAction::Set)
+                                             .value_parser(value_parser!(PathBuf)))
+                        .arg(policy.clone().help("Also report the chunks a policy file denies"));
+    let scan = Command::new("scan")
+                       .about("Look for hidden data in PNG files and score how suspicious each one is")
+                       .arg(arg!(<FILE> ... "The PNG files to scan")
+                                .value_parser(value_parser!(PathBuf)));
+    let analyze = Command::new("analyze")
+                          .about("Estimate the LSB embedding rate of a PNG file with chi-square, RS and sample pair analysis")
+                          .arg(arg!(-f --file "The PNG file to analyze").required(true)
+                                               .value_name("FILE")
+                                               .action(ArgAction::Set)
+                                               .value_parser(value_parser!(PathBuf)))
+                          .arg(arg!(-g --grid "Split the image into GRID x GRID regions for the breakdown")
+                                               .value_name("GRID")
+                                               .action(ArgAction::Set)
+                                               .value_parser(value_parser!(u16).range(1..))
+                                               .default_value("8"))
+                          .arg(arg!(--json "Print the results as JSON").action(ArgAction::SetTrue));
+    let scrub = Command::new("scrub")
+                        .about("Remove every covert channel from a PNG file while keeping its appearance")
+                        .arg(arg!(-f --file "The PNG file to scrub").required(true)
+                                             .value_name("FILE")
+                                             .action(ArgAction::Set)
+                                             .value_parser(value_parser!(PathBuf)))
+                        .arg(arg!(-o --out "The output file to write the scrubbed PNG to")
+                                             .value_name("FILE")
+                                             .action(ArgAction::Set)
+                                             .value_parser(value_parser!(PathBuf)))
+                        .arg(arg!(--keep "Also keep this ancillary chunk type (tRNS, gAMA, cHRM, sRGB and pHYs are always kept)")
+                                 .value_name("TYPE")
+                                 .action(ArgAction::Append)
+                                 .value_parser(value_parser!(String)))
+                        .arg(arg!(--"keep-animation" "Keep the APNG animation chunks").action(ArgAction::SetTrue))
+                        .arg(arg!(--pixels "Re-encode the pixels with random LSBs and fresh IDAT compression")
+                                 .action(ArgAction::SetTrue))
+                        .arg(policy.clone().help("Keep the ancillary chunks a policy file allows instead")
+                                   .conflicts_with_all(["keep", "keep-animation"]));
+    let remove = Command::new("remove")
+                         .about("Remove chunks of the given types, or the chunks a policy file denies")
+                         .arg(arg!(-f --file "The PNG file to remove chunks from").required(true)
+                                              .value_name("FILE")
+                                              .action(ArgAction::Set)
+                                              .value_parser(value_parser!(PathBuf)))
+                         .arg(arg!(-o --out "The output file to write the PNG to")
+                                              .value_name("FILE")
+                                              .action(ArgAction::Set)
+                                              .value_parser(value_parser!(PathBuf)))
+                         .arg(arg!(-t --type "A chunk type to remove")
+                                              .value_name("TYPE")
+                                              .action(ArgAction::Append)
+                                              .value_parser(value_parser!(String)))
+                         .arg(policy)
+                         .group(ArgGroup::new("what").args(["type", "policy"])
+                                                     .multiple(false)
+                                                     .required(true));
+    let sign = Command::new("sign")
+                       .about("Sign the critical chunks and chosen ancillary chunks of a PNG file with an Ed25519 key")
+                       .arg(arg!(-f --file "The PNG file to sign").required(true)
+                                            .value_name("FILE")
+                                            .action(ArgAction::Set)
+                                            .value_parser(value_parser!(PathBuf)))
+                       .arg(arg!(-k --key "The private key file").required(true)
+                                           .value_name("KEY")
+                                           .action(ArgAction::Set)
+                                           .value_parser(value_parser!(PathBuf)))
+                       .arg(arg!(--include "Also sign the chunks of this ancillary type")
+                                .value_name("TYPE")
+                                .action(ArgAction::Append)
+                                .value_parser(value_parser!(String)))
+                       .arg(arg!(-o --out "The output file to write the signed PNG to")
+                                           .value_name("FILE")
+                                           .action(ArgAction::Set)
+                                           .value_parser(value_parser!(PathBuf)));
+    let verify = Command::new("verify")
+                         .about("Verify the signature of a PNG file and show which chunks it covers")
+                         .arg(arg!(-f --file "The PNG file to verify").required(true)
+                                              .value_name("FILE")
+                                              .action(ArgAction::Set)
+                                              .value_parser(value_parser!(PathBuf)))
+                         .arg(arg!(-k --key "The public key the file must be signed with")
+                                             .value_name("KEY")
+                                             .action(ArgAction::Set)
+                                             .value_parser(value_parser!(PathBuf)));
+    let key_format = arg!(--format "The key encoding")
+                                            .value_name("FORMAT")
+                                            .action(ArgAction::Set)
+                                            .value_parser(["pem", "hex", "raw"])
+                                            .default_value("pem");
+    let key = Command::new("key")
+                      .about("Generate, import and export Ed25519 signing keys")
+                      .subcommand_required(true)
+                      .subcommand(Command::new("generate")
+                                          .about("Generate a key pair, written to FILE and FILE.pub")
+                                          .arg(arg!(-o --out "The private key file to write").required(true)
+                                                               .value_name("FILE")
+                                                               .action(ArgAction::Set)
+                                                               .value_parser(value_parser!(PathBuf))))
+                      .subcommand(Command::new("import")
+                                          .about("Import a private key in PEM, hex or raw form, written to FILE and FILE.pub")
+                                          .arg(arg!(-i --input "The key to import").required(true)
+                                                                 .value_name("KEY")
+                                                                 .action(ArgAction::Set)
+                                                                 .value_parser(value_parser!(PathBuf)))
+                                          .arg(arg!(-o --out "The private key file to write").required(true)
+                                                               .value_name("FILE")
+                                                               .action(ArgAction::Set)
+                                                               .value_parser(value_parser!(PathBuf))))
+                      .subcommand(Command::new("export")
+                                          .about("Export a private key, or its public key, in PEM, hex or raw form")
+                                          .arg(arg!(-k --key "The private key file").required(true)
+                                                              .value_name("KEY")
+                                                              .action(ArgAction::Set)
+                                                              .value_parser(value_parser!(PathBuf)))
+                                          .arg(arg!(--public "Export the public key").action(ArgAction::SetTrue))
+                                          .arg(key_format)
+                                          .arg(arg!(-o --out "The file to write instead of standard output")
+                                                               .value_name("FILE")
+                                                               .action(ArgAction::Set)
+                                                               .value_parser(value_parser!(PathBuf))));
+    let keygen = Command::new("keygen")
+                         .about("Generate an X25519 identity for encryption, written to FILE and its recipient to FILE.pub")
+                         .arg(arg!(-o --out "The identity file to write").required(true)
+                                              .value_name("FILE")
+                                              .action(ArgAction::Set)
+                                              .value_parser(value_parser!(PathBuf)));
+    let fingerprint = Command::new("fingerprint")
+                              .about("Mark a copy of a PNG file with a recipient ID, in a chunk and in the pixels")
+                              .arg(arg!(-f --file "The PNG file to mark").required(true)
+                                                   .value_name("FILE")
+                                                   .action(ArgAction::Set)
+                                                   .value_parser(value_parser!(PathBuf)))
+                              .arg(arg!(--id "The recipient ID, at most 31 bytes").required(true)
+                                       .value_name("ID")
+                                       .action(ArgAction::Set)
+                                       .value_parser(value_parser!(String)))
+                              .arg(arg!(--secret "Read the fingerprint secret from SOURCE: prompt (default), env:VAR, fd:N or file:PATH")
+                                       .value_name("SOURCE")
+                                       .num_args(0..=1)
+                                       .default_missing_value(secret::PROMPT)
+                                       .default_value(secret::PROMPT)
+                                       .action(ArgAction::Set)
+                                       .value_parser(value_parser!(String)))
+                              .arg(arg!(-o --out "The output file to write the marked PNG to")
+                                                  .value_name("FILE")
+                                                  .action(ArgAction::Set)
+                                                  .value_parser(value_parser!(PathBuf)));
+    let trace = Command::new("trace")
+                        .about("Recover the recipient ID from a fingerprinted PNG file, even with its metadata stripped")
+                        .arg(arg!(-f --file "The suspect PNG file").required(true)
+                                             .value_name("FILE")
+                                             .action(ArgAction::Set)
+                                             .value_parser(value_parser!(PathBuf)))
+                        .arg(arg!(--secret "Read the fingerprint secret from SOURCE: prompt (default), env:VAR, fd:N or file:PATH")
+                                 .value_name("SOURCE")
+                                 .num_args(0..=1)
+                                 .default_missing_value(secret::PROMPT)
+                                 .default_value(secret::PROMPT)
+                                 .action(ArgAction::Set)
+                                 .value_parser(value_parser!(String)));
+    let hash = Command::new("hash")
+                       .about("Print a digest of the image content that ignores metadata and how the image is stored")
+                       .arg(arg!(<FILE> ... "The PNG files to hash")
+                                .value_parser(value_parser!(PathBuf)))
+                       .arg(arg!(--level "Hash the critical chunks, or the decoded pixels which also ignores compression")
+                                .value_name("LEVEL")
+                                .action(ArgAction::Set)
+                                .value_parser(["chunks", "pixels"])
+                                .default_value("pixels"));
+    let diff = Command::new("diff")
+                       .about("Show the chunks added, removed, moved and modified between two PNG files")
+                       .arg(arg!(<A> "The original PNG file").value_parser(value_parser!(PathBuf)))
+                       .arg(arg!(<B> "The changed PNG file").value_parser(value_parser!(PathBuf)))
+                       .arg(arg!(--pixels "Also compare the decoded pixels").action(ArgAction::SetTrue));
+    let inventory = Command::new("inventory")
+                            .about("List every deepng payload in a PNG file with its name, location and size")
+                            .arg(arg!(-f --file "The PNG file to inspect").required(true)
+                                                 .value_name("FILE")
+                                                 .action(ArgAction::Set)
+                                                 .value_parser(value_parser!(PathBuf)));
+    let capacity = Command::new("capacity")
+                           .about("Report how many bytes each embedding method can hide in a PNG file")
+                           .arg(arg!([FILE] "The PNG file to inspect")
+                                    .value_parser(value_parser!(PathBuf)))
+                           .arg(arg!(-f --file "The PNG file to inspect")
+                                                .value_name("FILE")
+                                                .action(ArgAction::Set)
+                                                .value_parser(value_parser!(PathBuf)))
+                           .arg(arg!(--fec "Account for Reed-Solomon error correction with PARITY check bytes per block")
+                                    .value_name("PARITY")
+                                    .action(ArgAction::Set)
+                                    .value_parser(value_parser!(u8).range(fec::MIN_PARITY as i64..=fec::MAX_PARITY as i64)))
+                           .arg(arg!(--name "Account for a payload name")
+                                    .value_name("NAME")
+                                    .action(ArgAction::Set)
+                                    .value_parser(value_parser!(String)))
+                           .arg(arg!(--encrypted "Account for password encryption").action(ArgAction::SetTrue))
+                           .arg(arg!(--pad "Account for the largest padding a scheme can add")
+                                    .value_name("SCHEME")
+                                    .action(ArgAction::Set)
+                                    .value_parser(padding::Padding::from_str)
+                                    .requires("encrypted"))
+                           .group(ArgGroup::new("input").args(["FILE", "file"])
+                                                        .multiple(false)
+                                                        .required(true));
 
-    let matches = Command::new("deepng")
+    return Command::new("deepng")
                         .subcommand(encode)
                         .subcommand(decode)
-                        .get_matches();
+                        .subcommand(list)
+                        .subcommand(capacity)
+                        .subcommand(check)
+                        .subcommand(scan)
+                        .subcommand(analyze)
+                        .subcommand(scrub)
+                        .subcommand(remove)
+                        .subcommand(sign)
+                        .subcommand(verify)
+                        .subcommand(key)
+                        .subcommand(keygen)
+                        .subcommand(fingerprint)
+                        .subcommand(trace)
+                        .subcommand(hash)
+                        .subcommand(diff)
+                        .subcommand(inventory);
+}
 
+fn run(matches: &ArgMatches) -> Result<()> {
     // process argu
     match matches.subcommand() {
         Some(("encode", encode)) => {
-            let in_file = encode.get_one::<PathBuf>("file").unwrap();
-            let ck_type = encode.get_one::<String>("type").unwrap();
-            // let messages = encode.get_one::<PathBuf>("messages");
-            let mut data = "".to_string();
+            let mut data: Vec<u8> = Vec::new();
+            let mut options = container::PackOptions {
+                compress: encode.get_flag("deflate"),
+                fec     : encode.get_one::<u8>("fec").copied(),
+                padding : encode.get_one::<padding::Padding>("pad").cloned(),
+                ..Default::default()
+            };
             if let Some(msg_file) = encode.get_one::<PathBuf>("msg_file") {
-                data = fs::read_to_string(msg_file)?;
+                data = fs::read(msg_file)?;
+                options.name = msg_file.file_name().map(|name| name.to_string_lossy().to_string());
+                options.mime_type = options.name.as_deref().and_then(container::mime_type).map(str::to_string);
+            } else if let Some(dir) = encode.get_one::<PathBuf>("dir") {
+                data = archive::pack_dir(dir)?;
+                options.name = dir.canonicalize()?.file_name().map(|name| name.to_string_lossy().to_string());
+                options.mime_type = Some(archive::MIME_TYPE.to_string());
+                // tar pads every file to 512 bytes
+                options.compress = true;
             } else if let Some(message) = encode.get_one::<String>("message") {
-                data = message.to_string();
+                data = message.as_bytes().to_vec();
             }
-            let out_file = encode.get_one::<PathBuf>("out");
-            let content = fs::read(&in_file)?;
-            let mut png = png::Png::try_from(content.as_ref())?;
-            let ck_type = chunk_type::ChunkType::from_str(ck_type)?;
-            if ck_type.is_valid_type() == false {
-                return Err(Box::from(png_error::PngError::InvalidEncodeType));
-            }
-            let ck = chunk::Chunk::new(ck_type, data.as_bytes().to_vec());
-
-            png.append_chunk(ck);
-            match out_file {
-                Some(out_file) => {
-                    let mut ofile = fs::File::create(&out_file)?;
-                    ofile.write_all(&png.as_bytes())?;
+            // an encrypted payload keeps the file name inside and is only
+            // named in the clear when --name asks for it
+            let name = encode.get_one::<String>("name").cloned();
+            let (data, name) = match (encode.get_one::<String>("password"), encode.get_many::<String>("recipient")) {
+                (Some(source), _) => {
+                    let password = secret::read(source, "Password", true)?;
+                    (seal_payload(encode, &data, options, name.as_deref(), &password)?, name)
+                }
+                (None, Some(recipients)) => {
+                    let recipients = recipients.map(|r| keys::recipient_arg(r)).collect::<Result<Vec<_>>>()?;
+                    (seal_for_recipients(&data, options, name.as_deref(), &recipients)?, name)
+                }
+                (None, None) => {
+                    options.name = name.or(options.name);
+                    (container::pack(&data, &options)?, options.name)
+                }
+            };
+            // read once, even for several carriers
+            let ck_type = match encode.get_one::<String>("method").unwrap().as_str() {
+                "chunk" => Some(chunk_type_arg(encode, true)?),
+                _ => None,
+            };
+            match encode.get_many::<PathBuf>("spread") {
+                Some(carriers) => {
+                    let carriers: Vec<&PathBuf> = carriers.collect();
+                    let fragments = match encode.get_one::<u8>("shares") {
+                        Some(&count) if count as usize != carriers.len() => {
+                            return Err(Box::from(shamir_error::ShamirError::CarrierCountMismatch(count as usize, carriers.len())));
+                        }
+                        Some(&count) => {
+                            let threshold = *encode.get_one::<u8>("threshold").unwrap();
+                            shamir::split(&data, count as usize, threshold as usize, name.as_deref())?
+                        }
+                        None => spread::split(&data, carriers.len(), name.as_deref())?,
+                    };
+                    for (carrier, fragment) in carriers.into_iter().zip(fragments) {
+                        embed_payload(encode, ck_type, fragment, name.as_deref(), carrier, None)?;
+                    }
                 }
                 None => {
-                    let mut ofile = fs::File::create(&in_file)?;
-                    ofile.write_all(&png.as_bytes())?;
+                    let in_file = encode.get_one::<PathBuf>("file").unwrap();
+                    embed_payload(encode, ck_type, data, name.as_deref(), in_file, encode.get_one::<PathBuf>("out"))?;
                 }
             }
         }
 
         Some(("decode", decode)) => {
-            let in_file = decode.get_one::<PathBuf>("file").unwrap();
-            let ck_type = decode.get_one::<String>("type").unwrap();
             let msg_file = decode.get_one::<PathBuf>("msg_file");
+            let extract_to = decode.get_one::<PathBuf>("extract-to");
+            let identity = decode.get_one::<PathBuf>("identity").map(|i| keys::read_identity(i)).transpose()?;
+            let password = decode.get_one::<String>("password").map(|s| secret::read(s, "Password", false)).transpose()?;
+            let credentials = crypto::Credentials {
+                password: password.as_ref().map(secret::Secret::as_str),
+                identity: identity.as_ref(),
+            };
+            if let Some(files) = decode.get_many::<PathBuf>("spread") {
+                let data = join_spread(files.collect())?;
+                return write_message(&unpack_payload(data, &credentials)?, msg_file, extract_to);
+            }
+            let in_file = decode.get_one::<PathBuf>("file").unwrap();
+            let method = decode.get_one::<String>("method").unwrap();
             let out_file = decode.get_one::<PathBuf>("out");
             let content = fs::read(in_file)?;
             let mut png = png::Png::try_from(content.as_ref())?;
-            let ck = png.remove_last_chunk(ck_type);
-            match ck {
-                Ok(ck) => {
-                    match msg_file {
-                        Some(msg_file) => {
-                            let mut ofile = fs::File::create(&msg_file)?;
-                            ofile.write_all(ck.data())?;
-                        }
-                        None => {
-                            println!("{}", ck.data_as_string()?);
-                        }
+            let data = match method.as_str() {
+                _ if decode.contains_id("name") => {
+                    let name = decode.get_one::<String>("name").unwrap();
+                    let found = discover::find_by_name(&png, name)
+                                         .ok_or(png_error::PngError::UnknownPayloadName(name.to_string()))?;
+                    discover::remove(&mut png, found.location());
+                    Ok(found.raw().to_vec())
+                }
+                _ if decode.get_flag("auto") => {
+                    match discover_payload(&mut png, decode.get_one::<usize>("pick").copied())? {
+                        Some(data) => Ok(data),
+                        None => return Ok(()),
+                    }
+                }
+                "text" => {
+                    let keyword = decode.get_one::<String>("keyword").unwrap();
+                    png.remove_last_text_chunk(keyword, |tc| Ok(cover_text::decode(tc.text())?))
+                }
+                "fctl" => fctl_stego::extract(&png),
+                "trailer" => {
+                    match png.take_trailer() {
+                        trailer if trailer.is_empty() => Err(Box::from(png_error::PngError::InvalidPayload)),
+                        trailer => Ok(trailer),
                     }
+                }
+                _ => {
+                    let ck_type = chunk_type_arg(decode, false)?;
+                    png.remove_last_chunk(&ck_type.to_string()).map(|ck| ck.data().to_vec())
+                }
+            };
+            // the payload only leaves the file once it has been recovered
+            let data = unpack_payload(data?, &credentials)?;
+            write_message(&data, msg_file, extract_to)?;
+            write_png(&png, in_file, out_file)?;
+        }
+
+        Some(("list", list)) => {
+            let in_file = list.get_one::<PathBuf>("file").unwrap();
+            let content = fs::read(in_file)?;
+            let png = png::Png::try_from(content.as_ref())?;
+            list_chunks(&png)?;
+        }
+
+        Some(("capacity", capacity)) => {
+            let in_file = capacity.get_one::<PathBuf>("FILE")
+                                  .or(capacity.get_one::<PathBuf>("file"))
+                                  .unwrap();
+            let content = fs::read(in_file)?;
+            let png = png::Png::try_from(content.as_ref())?;
+            let options = container::PackOptions {
+                name     : capacity.get_one::<String>("name").cloned(),
+                fec      : capacity.get_one::<u8>("fec").copied(),
+                encrypted: capacity.get_flag("encrypted"),
+                padding  : capacity.get_one::<padding::Padding>("pad").cloned(),
+                ..Default::default()
+            };
+            report_capacity(&png, &options)?;
+        }
+
+        Some(("check", check)) => {
+            let in_file = check.get_one::<PathBuf>("file").unwrap();
+            let content = fs::read(in_file)?;
+            let png = png::Png::try_from(content.as_ref())?;
+            let mut findings = check::check(&png);
+            if let Some(policy_file) = check.get_one::<PathBuf>("policy") {
+                findings.extend(check::check_policy(&png, &read_policy(policy_file)?));
+            }
+            for finding in &findings {
+                println!("{}", finding);
+            }
+            if findings.is_empty() {
+                println!("ok");
+            }
+        }
 
+        Some(("scan", scan)) => {
+            for in_file in scan.get_many::<PathBuf>("FILE").unwrap() {
+                let content = fs::read(in_file)?;
+                let png = match png::Png::try_from(content.as_ref()) {
+                    Ok(png) => png,
+                    Err(e) => {
+                        println!("{}: not a valid PNG ({})", in_file.display(), e);
+                        continue;
+                    }
+                };
+                let report = scan::scan(&png);
+                println!("{}: score {}", in_file.display(), report.score());
+                for finding in report.findings() {
+                    println!("  {}", finding);
                 }
-                Err(e) => {
-                    eprintln!("Error: {}", e);
+            }
+        }
+
+        Some(("analyze", analyze)) => {
+            let in_file = analyze.get_one::<PathBuf>("file").unwrap();
+            let grid = *analyze.get_one::<u16>("grid").unwrap() as usize;
+            let content = fs::read(in_file)?;
+            let png = png::Png::try_from(content.as_ref())?;
+            let analysis = steganalysis::analyze(&pixels::Pixels::decode(&png)?, grid)?;
+            if analyze.get_flag("json") {
+                println!("{}", analysis.to_json());
+            } else {
+                println!("{}", analysis);
+            }
+        }
+
+        Some(("scrub", scrub)) => {
+            let in_file = scrub.get_one::<PathBuf>("file").unwrap();
+            let out_file = scrub.get_one::<PathBuf>("out");
+            let content = fs::read(in_file)?;
+            let png = png::Png::try_from(content.as_ref())?;
+            let mut keep: Vec<String> = scrub::DEFAULT_KEEP.iter().map(|s| s.to_string()).collect();
+            if let Some(types) = scrub.get_many::<String>("keep") {
+                keep.extend(types.cloned());
+            }
+            let options = scrub::ScrubOptions {
+                keep,
+                keep_animation: scrub.get_flag("keep-animation"),
+                pixels: scrub.get_flag("pixels"),
+                policy: scrub.get_one::<PathBuf>("policy").map(read_policy).transpose()?,
+            };
+            let (clean, report) = scrub::scrub(&png, &options)?;
+            if let Some(e) = report.broken_animation() {
+                println!("dropped the animation, its frame controls can't be cleared: {}", e);
+            }
+            for (name, length) in report.removed() {
+                println!("removed {} chunk ({} bytes)", name, length);
+            }
+            if report.trailer() > 0 {
+                println!("removed {} bytes after IEND", report.trailer());
+            }
+            if report.pixels() {
+                println!("re-encoded pixels, randomized {} sample LSBs", report.lsb_samples());
+            }
+            write_png(&clean, in_file, out_file)?;
+        }
+
+        Some(("remove", remove)) => {
+            let in_file = remove.get_one::<PathBuf>("file").unwrap();
+            let out_file = remove.get_one::<PathBuf>("out");
+            let content = fs::read(in_file)?;
+            let png = png::Png::try_from(content.as_ref())?;
+            let policy = match remove.get_many::<String>("type") {
+                Some(types) => {
+                    let types = types.map(|t| chunk_type::ChunkType::from_str(t))
+                                     .collect::<std::result::Result<Vec<_>, _>>()?;
+                    policy::Policy::deny(&types)
                 }
+                None => read_policy(remove.get_one::<PathBuf>("policy").unwrap())?,
+            };
+            let (clean, removed) = scrub::remove(&png, &policy);
+            for (name, length) in &removed {
+                println!("removed {} chunk ({} bytes)", name, length);
+            }
+            if removed.is_empty() {
+                println!("nothing to remove");
+            }
+            write_png(&clean, in_file, out_file)?;
+        }
+
+        Some(("sign", sign)) => {
+            let in_file = sign.get_one::<PathBuf>("file").unwrap();
+            let out_file = sign.get_one::<PathBuf>("out");
+            let content = fs::read(in_file)?;
+            let mut png = png::Png::try_from(content.as_ref())?;
+            let key = keys::read_signing_key(sign.get_one::<PathBuf>("key").unwrap())?;
+            let included = match sign.get_many::<String>("include") {
+                Some(types) => types.map(|t| chunk_type::ChunkType::from_str(t))
+                                    .collect::<std::result::Result<Vec<_>, _>>()?,
+                None => Vec::new(),
+            };
+            signing::sign(&mut png, &key, &included)?;
+            println!("signed with {}", hex::encode(key.verifying_key().as_bytes()));
+            write_png(&png, in_file, out_file)?;
+        }
+
+        Some(("verify", verify)) => {
+            let in_file = verify.get_one::<PathBuf>("file").unwrap();
+            let content = fs::read(in_file)?;
+            let png = png::Png::try_from(content.as_ref())?;
+            let trusted = verify.get_one::<PathBuf>("key").map(|k| keys::read_verifying_key(k)).transpose()?;
+            let verification = signing::verify(&png, trusted.as_ref())?;
+            println!("signature ok");
+            println!("key: {}{}", hex::encode(verification.public_key().as_bytes()),
+                     if verification.trusted() { "" } else { " (not checked against a trusted key)" });
+            println!("digest: {}", hex::encode(verification.digest()));
+            for &i in verification.covered() {
+                println!("  covered     #{:<4} {}", i, png.chunks()[i].chunk_type());
             }
-            match out_file {
-                Some(out_file) => {
-                    let mut ofile = fs::File::create(&out_file)?;
-                    ofile.write_all(&png.as_bytes())?;
+            for &i in verification.uncovered() {
+                println!("  not covered #{:<4} {}", i, png.chunks()[i].chunk_type());
+            }
+            if !png.trailer().is_empty() {
+                println!("  not covered {} bytes after IEND", png.trailer().len());
+            }
+        }
+
+        Some(("key", key)) => {
+            match key.subcommand() {
+                Some(("generate", generate)) => {
+                    let out_file = generate.get_one::<PathBuf>("out").unwrap();
+                    write_key_pair(&keys::generate(), out_file)?;
                 }
-                None => {
-                    let mut ofile = fs::File::create(&in_file)?;
-                    ofile.write_all(&png.as_bytes())?;
+                Some(("import", import)) => {
+                    let signing_key = keys::read_signing_key(import.get_one::<PathBuf>("input").unwrap())?;
+                    write_key_pair(&signing_key, import.get_one::<PathBuf>("out").unwrap())?;
+                }
+                Some(("export", export)) => {
+                    let signing_key = keys::read_signing_key(export.get_one::<PathBuf>("key").unwrap())?;
+                    let format = keys::KeyFormat::from_str(export.get_one::<String>("format").unwrap())?;
+                    let public = export.get_flag("public");
+                    let content = match public {
+                        true  => zeroize::Zeroizing::new(keys::encode_verifying_key(&signing_key.verifying_key(), format)?),
+                        false => keys::encode_signing_key(&signing_key, format)?,
+                    };
+                    match export.get_one::<PathBuf>("out") {
+                        Some(out_file) if public => fs::write(out_file, content)?,
+                        Some(out_file) => keys::write_secret(out_file, &content)?,
+                        None => std::io::stdout().write_all(&content)?,
+                    }
+                }
+                _ => unreachable!("Unrecognized subcommand")
+            }
+        }
+
+        Some(("keygen", keygen)) => {
+            let out_file = keygen.get_one::<PathBuf>("out").unwrap();
+            let public_file = keys::public_key_path(out_file)?;
+            let identity = keys::generate_identity();
+            let recipient = keys::encode_recipient(&x25519_dalek::PublicKey::from(&identity));
+            keys::write_secret(out_file, keys::encode_identity(&identity).as_bytes())?;
+            fs::write(&public_file, &recipient)?;
+            println!("identity: {}", out_file.display());
+            println!("recipient: {} ({})", public_file.display(), recipient.trim_end());
+        }
+
+        Some(("fingerprint", fingerprint)) => {
+            let in_file = fingerprint.get_one::<PathBuf>("file").unwrap();
+            let out_file = fingerprint.get_one::<PathBuf>("out");
+            let content = fs::read(in_file)?;
+            let mut png = png::Png::try_from(content.as_ref())?;
+            let secret = secret::read(fingerprint.get_one::<String>("secret").unwrap(), "Fingerprint secret", true)?;
+            let key = fingerprint::FingerprintKey::derive(secret.as_str());
+            let id = fingerprint.get_one::<String>("id").unwrap();
+            match fingerprint::fingerprint(&mut png, id, &key)? {
+                Some(copies) => println!("marked for {}: chunk and {} copies in the pixels", id, copies),
+                None => println!("marked for {}: chunk only, the pixels are too few or not 8 or 16-bit samples", id),
+            }
+            write_png(&png, in_file, out_file)?;
+        }
+
+        Some(("trace", trace)) => {
+            let in_file = trace.get_one::<PathBuf>("file").unwrap();
+            let content = fs::read(in_file)?;
+            let png = png::Png::try_from(content.as_ref())?;
+            let secret = secret::read(trace.get_one::<String>("secret").unwrap(), "Fingerprint secret", false)?;
+            let found = fingerprint::trace(&png, &fingerprint::FingerprintKey::derive(secret.as_str()))?;
+            if found.chunk().is_none() && found.pixels().is_none() {
+                return Err(Box::from(fingerprint_error::FingerprintError::NotFound));
+            }
+            println!("chunk: {}", found.chunk().unwrap_or("not found"));
+            match found.pixels() {
+                Some(pixels) => println!("pixels: {} ({} copies, {:.1}% of bits agree)",
+                                         pixels.id(), pixels.copies(), 100.0 * pixels.agreement()),
+                None => println!("pixels: not found"),
+            }
+            if let (Some(chunk), Some(pixels)) = (found.chunk(), found.pixels()) && chunk != pixels.id() {
+                println!("warning: the chunk and the pixels name different recipients");
+            }
+        }
+
+        Some(("hash", hash)) => {
+            let level = hash.get_one::<String>("level").unwrap();
+            for in_file in hash.get_many::<PathBuf>("FILE").unwrap() {
+                let content = fs::read(in_file)?;
+                let digest = png::Png::try_from(content.as_ref()).and_then(|png| {
+                    match level.as_str() {
+                        "chunks" => content_hash::chunk_digest(&png),
+                        _        => content_hash::pixel_digest(&png),
+                    }
+                });
+                match digest {
+                    Ok(digest) => println!("{}  {}", hex::encode(digest), in_file.display()),
+                    Err(e) => eprintln!("{}: {}", in_file.display(), e),
                 }
             }
         }
+
+        Some(("diff", diff)) => {
+            let old = png::Png::try_from(fs::read(diff.get_one::<PathBuf>("A").unwrap())?.as_ref())?;
+            let new = png::Png::try_from(fs::read(diff.get_one::<PathBuf>("B").unwrap())?.as_ref())?;
+            println!("{}", diff::diff(&old, &new));
+            if diff.get_flag("pixels") {
+                println!("{}", diff::diff_pixels(&old, &new)?);
+            }
+        }
+
+        Some(("inventory", inventory)) => {
+            let in_file = inventory.get_one::<PathBuf>("file").unwrap();
+            let content = fs::read(in_file)?;
+            let png = png::Png::try_from(content.as_ref())?;
+            let found = discover::discover(&png);
+            for (n, f) in found.iter().enumerate() {
+                println!("{}: {}", n + 1, f);
+            }
+            if found.is_empty() {
+                println!("no payloads");
+            }
+        }
+
         _ => unreachable!("Unrecognized subcommand")
     }
 
     return Ok(());
 }
 
+
+fn list_chunks(png: &png::Png) -> Result<()> {
+    let frames = apng::frames(png)?;
+    let mut frame_of: Vec<Option<usize>> = vec![None; png.chunks().len()];
+    for (n, frame) in frames.iter().enumerate() {
+        for &i in frame.chunks() {
+            frame_of[i] = Some(n);
+        }
+    }
+    let mut current: Option<usize> = None;
+    for (i, ck) in png.chunks().iter().enumerate() {
+        if frame_of[i] != current {
+            if let Some(n) = frame_of[i] {
+                let fctl = frames[n].control();
+                let (num, den) = fctl.delay();
+                println!("frame {}: {}x{} at ({}, {}), delay {}/{}",
+                         n, fctl.width(), fctl.height(), fctl.x_offset(), fctl.y_offset(), num, den);
+            }
+            current = frame_of[i];
+        }
+        let indent = if current.is_some() { "  " } else { "" };
+        println!("{}#{:<4} {} {:>8}", indent, i, ck.chunk_type(), ck.length());
+    }
+    match apng::animation_control(png).and_then(|actl| apng::validate(png).map(|()| actl)) {
+        Ok(Some(actl)) if actl.num_plays() == 0 => println!("animation: {} frames, looping, sequence ok", actl.num_frames()),
+        Ok(Some(actl)) => println!("animation: {} frames, {} plays, sequence ok", actl.num_frames(), actl.num_plays()),
+        Ok(None) => {}
+        Err(e) => println!("animation: {}", e),
+    }
+    return Ok(());
+}
+
+fn report_capacity(png: &png::Png, options: &container::PackOptions) -> Result<()> {
+    let header = ihdr::ImageHeader::try_from(png.chunk_by_type("IHDR").ok_or(png_error::PngError::InvalidImageHeader)?)?;
+    println!("image: {}x{}, {}-bit, color type {}, {} channels",
+             header.width(), header.height(), header.bit_depth(), header.color_type(), header.channels());
+    match options.fec {
+        Some(parity) => println!("overhead: {} bytes, plus {} parity bytes per 255-byte block",
+                                 capacity::payload_size(0, options), parity),
+        None => println!("overhead: {} bytes", capacity::payload_size(0, options)),
+    }
+    for method in capacity::capacity(png, options)? {
+        println!("{}", method);
+        if method.method() == "fctl" && apng::is_animated(png) {
+            for slot in &fctl_stego::slots(png)? {
+                println!("  frame {}: {} delay bits, blend {}, dispose {}",
+                         slot.frame(), slot.delay_bits(), slot.blend() as u8, slot.dispose() as u8);
+            }
+        }
+    }
+    return Ok(());
+}
+
+fn chunk_type_arg(matches: &ArgMatches, confirm: bool) -> Result<chunk_type::ChunkType> {
+    if let Some(source) = matches.get_one::<String>("key") {
+        let key = secret::read(source, "Chunk key", confirm)?;
+        return Ok(camouflage::chunk_type_from_key(key.as_str()));
+    }
+    let ck_type = matches.get_one::<String>("type")
+                         .ok_or(png_error::PngError::MissingChunkType)?;
+    return Ok(chunk_type::ChunkType::from_str(ck_type)?);
+}
+
+// Lists the payloads found in `png` and takes out the chosen one, or the
+// only one. Returns None when the user still has to choose.
+fn discover_payload(png: &mut png::Png, pick: Option<usize>) -> Result<Option<Vec<u8>>> {
+    let found = discover::discover(png);
+    if found.is_empty() {
+        return Err(Box::from(png_error::PngError::InvalidPayload));
+    }
+    let chosen = match (pick, found.len()) {
+        (Some(n), _) => Some(n),
+        (None, 1) => Some(1),
+        (None, _) => None,
+    };
+    for (n, f) in found.iter().enumerate() {
+        match chosen {
+            None => println!("{}: {}", n + 1, f),
+            Some(_) => eprintln!("{}: {}", n + 1, f),
+        }
+    }
+    let n = match chosen {
+        Some(n) => n,
+        None => {
+            println!("{} payloads found, choose one with --pick", found.len());
+            return Ok(None);
+        }
+    };
+    let f = found.get(n.wrapping_sub(1)).ok_or(png_error::PngError::InvalidPayload)?;
+    discover::remove(png, f.location());
+    return Ok(Some(f.raw().to_vec()));
+}
+
+// Embeds a packed payload into `in_file` with the method chosen on the
+// command line, replacing any payload with the same name. `ck_type` is the
+// chunk type for the chunk method.
+fn embed_payload(encode: &ArgMatches, ck_type: Option<chunk_type::ChunkType>, data: Vec<u8>, name: Option<&str>,
+                 in_file: &PathBuf, out_file: Option<&PathBuf>) -> Result<()> {
+    let method = encode.get_one::<String>("method").unwrap();
+    let content = fs::read(in_file)?;
+    let mut png = png::Png::try_from(content.as_ref())?;
+    if let Some(name) = name && discover::remove_by_name(&mut png, name) > 0 {
+        eprintln!("replacing the payload named \"{}\"", name);
+    }
+    match method.as_str() {
+        "text" => {
+            let keyword = encode.get_one::<String>("keyword").unwrap();
+            let kind = match encode.get_flag("compress") {
+                true => TextKind::Compressed,
+                false => TextKind::International,
+            };
+            let tc = TextChunk::new(kind, keyword, &cover_text::encode(&data))?;
+            png.insert_chunk(apng::hidden_chunk_position(&png), tc.to_chunk());
+        }
+        "fctl" => {
+            fctl_stego::embed(&mut png, &data)?;
+        }
+        "trailer" => {
+            if !png.trailer().is_empty() && !encode.get_flag("replace") {
+                return Err(Box::from(png_error::PngError::TrailerInUse(png.trailer().len())));
+            }
+            png.set_trailer(data);
+        }
+        _ => {
+            let ck_type = ck_type.ok_or(png_error::PngError::MissingChunkType)?;
+            if ck_type.is_valid_type() == false {
+                return Err(Box::from(png_error::PngError::InvalidEncodeType));
+            }
+            let ck = chunk::Chunk::new(ck_type, data);
+            png.insert_chunk(apng::hidden_chunk_position(&png), ck);
+        }
+    }
+    write_png(&png, in_file, out_file)?;
+    return Ok(());
+}
+
+// Collects the fragments of a spread payload, or the shares of a split one,
+// from every file and joins them. The files are left as they are.
+fn join_spread(files: Vec<&PathBuf>) -> Result<Vec<u8>> {
+    let mut fragments: Vec<Vec<u8>> = Vec::new();
+    let mut shares: Vec<Vec<u8>> = Vec::new();
+    for in_file in files {
+        let content = fs::read(in_file)?;
+        let png = png::Png::try_from(content.as_ref())?;
+        let mut found = false;
+        for payload in discover::discover(&png).iter().filter_map(|f| f.payload().ok()) {
+            if payload.flags() & container::FRAGMENTED != 0 {
+                fragments.push(payload.data().to_vec());
+                found = true;
+            } else if payload.flags() & container::SHARE != 0 {
+                shares.push(payload.data().to_vec());
+                found = true;
+            }
+        }
+        if !found {
+            eprintln!("{}: no fragment or share found", in_file.display());
+        }
+    }
+    return match (fragments.is_empty(), shares.is_empty()) {
+        (true, false) => shamir::combine(&shares),
+        (_, true) => spread::join(&fragments),
+        (false, false) => Err(Box::from(spread_error::SpreadError::MixedSets)),
+    };
+}
+
+// Writes a decoded message to a file, unpacks it into a directory or prints it.
+fn write_message(data: &[u8], msg_file: Option<&PathBuf>, extract_to: Option<&PathBuf>) -> Result<()> {
+    match (msg_file, extract_to) {
+        (_, Some(dest)) => {
+            for path in archive::unpack(data, dest)? {
+                println!("{}", dest.join(path).display());
+            }
+        }
+        (Some(msg_file), None) => {
+            let mut ofile = fs::File::create(msg_file)?;
+            ofile.write_all(data)?;
+        }
+        (None, None) => {
+            println!("{}", std::str::from_utf8(data)?);
+        }
+    }
+    return Ok(());
+}
+
+// Packs the message and the decoy, if there is one, seals them with their
+// passwords and wraps the result in a container of its own, named `name` in
+// the clear. Error correction goes on the outside, where the damage happens.
+fn seal_payload(encode: &ArgMatches, data: &[u8], mut options: container::PackOptions, name: Option<&str>,
+                password: &secret::Secret) -> Result<Vec<u8>> {
+    let fec = options.fec.take();
+    let inner = container::pack(data, &options)?;
+    let decoy = match (encode.get_one::<PathBuf>("decoy-file"), encode.get_one::<String>("decoy-message")) {
+        (Some(decoy_file), _) => {
+            let name = decoy_file.file_name().map(|name| name.to_string_lossy().to_string());
+            let mime_type = name.as_deref().and_then(container::mime_type).map(str::to_string);
+            Some(container::pack(&fs::read(decoy_file)?, &container::PackOptions { name, mime_type, ..options })?)
+        }
+        (None, Some(message)) => {
+            let options = container::PackOptions { name: None, mime_type: None, ..options };
+            Some(container::pack(message.as_bytes(), &options)?)
+        }
+        (None, None) => None,
+    };
+    let decoy_password = match decoy {
+        Some(_) => Some(secret::read(encode.get_one::<String>("decoy-password").unwrap(), "Decoy password", true)?),
+        None => None,
+    };
+    let decoy = decoy.as_deref().zip(decoy_password.as_ref().map(secret::Secret::as_str));
+    let sealed = crypto::seal(&inner, password.as_str(), decoy, &crypto::KdfParams::default())?;
+    return container::pack(&sealed, &outer_options(name, fec));
+}
+
+// Packs the message and seals it for the recipients, wrapped and protected
+// like seal_payload does.
+fn seal_for_recipients(data: &[u8], mut options: container::PackOptions, name: Option<&str>,
+                       recipients: &[x25519_dalek::PublicKey]) -> Result<Vec<u8>> {
+    let fec = options.fec.take();
+    let sealed = crypto::seal_for(&container::pack(data, &options)?, recipients)?;
+    return container::pack(&sealed, &outer_options(name, fec));
+}
+
+// The container around a sealed payload: only its name, if any, is readable.
+fn outer_options(name: Option<&str>, fec: Option<u8>) -> container::PackOptions {
+    return container::PackOptions { name: name.map(str::to_string), encrypted: true, fec, ..Default::default() };
+}
+
+// Unwraps a payload container, reporting what it says on stderr. Payloads
+// written before the container existed are returned as they are.
+fn unpack_payload(data: Vec<u8>, credentials: &crypto::Credentials) -> Result<Vec<u8>> {
+    if !container::is_container(&data) {
+        return Ok(data);
+    }
+    let payload = container::unpack(&data)?;
+    if payload.flags() & container::FEC != 0 {
+        eprintln!("fec: corrected {} bytes", payload.corrected());
+    }
+    if payload.flags() & container::ENCRYPTED != 0 {
+        return unpack_payload(crypto::unseal(payload.data(), credentials)?, &crypto::Credentials::default());
+    }
+    if payload.flags() & container::FRAGMENTED != 0 {
+        return Err(Box::from(spread_error::SpreadError::Fragment));
+    }
+    if payload.flags() & container::SHARE != 0 {
+        return Err(Box::from(shamir_error::ShamirError::Share));
+    }
+    if let Some(name) = payload.name() {
+        match payload.mime_type() {
+            Some(mime_type) => eprintln!("payload: {} ({}, {} bytes)", name, mime_type, payload.data().len()),
+            None => eprintln!("payload: {} ({} bytes)", name, payload.data().len()),
+        }
+    }
+    return Ok(payload.into_data());
+}
+
+fn read_policy(path: &PathBuf) -> Result<policy::Policy> {
+    return Ok(policy::Policy::from_str(&fs::read_to_string(path)?)?);
+}
+
+// Writes the private key to `out_file` and the public key next to it.
+fn write_key_pair(signing_key: &ed25519_dalek::SigningKey, out_file: &Path) -> Result<()> {
+    let public_file = keys::public_key_path(out_file)?;
+    keys::write_secret(out_file, &keys::encode_signing_key(signing_key, keys::KeyFormat::Pem)?)?;
+    fs::write(&public_file, keys::encode_verifying_key(&signing_key.verifying_key(), keys::KeyFormat::Pem)?)?;
+    println!("private key: {}", out_file.display());
+    println!("public key: {} ({})", public_file.display(), hex::encode(signing_key.verifying_key().as_bytes()));
+    return Ok(());
+}
+
+fn write_png(png: &png::Png, in_file: &PathBuf, out_file: Option<&PathBuf>) -> Result<()> {
+    let mut ofile = match out_file {
+        Some(out_file) => fs::File::create(out_file)?,
+        None => fs::File::create(in_file)?,
+    };
+    ofile.write_all(&png.as_bytes())?;
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("deepng-main-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn deepng(args: &[&str]) -> Result<()> {
+        return run(&cli().try_get_matches_from(["deepng"].iter().chain(args))?);
+    }
+
+    #[test]
+    fn test_name_with_encryption() {
+        let dir = temp_dir("name");
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        fs::write(dir.join("a.png"), pixels::tests::testing_png(8, 8, 2, 0).as_bytes()).unwrap();
+        fs::write(dir.join("password"), "open sesame").unwrap();
+        let password = format!("file:{}", path("password"));
+        for message in ["old", "new"] {
+            fs::write(dir.join("secret.txt"), message).unwrap();
+            deepng(&["encode", "-f", &path("a.png"), "-t", "ruSt", "-M", &path("secret.txt"),
+                     "--name", "cfg", "--password", &password]).unwrap();
+        }
+        let content = fs::read(dir.join("a.png")).unwrap();
+        let png = png::Png::try_from(content.as_ref()).unwrap();
+        let found = discover::discover(&png);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].name(), Some("cfg"));
+        deepng(&["decode", "-f", &path("a.png"), "--name", "cfg", "-M", &path("msg"), "--password", &password]).unwrap();
+        assert_eq!(fs::read(dir.join("msg")).unwrap(), b"new");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_trailer_replace() {
+        let dir = temp_dir("trailer");
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        let mut png = pixels::tests::testing_png(8, 8, 2, 0);
+        png.set_trailer(b"not ours".to_vec());
+        fs::write(dir.join("a.png"), png.as_bytes()).unwrap();
+        assert!(deepng(&["encode", "-f", &path("a.png"), "--method", "trailer", "-m", "hi"]).is_err());
+        assert_eq!(fs::read(dir.join("a.png")).unwrap(), png.as_bytes());
+        deepng(&["encode", "-f", &path("a.png"), "--method", "trailer", "-m", "hi", "--replace"]).unwrap();
+        deepng(&["decode", "-f", &path("a.png"), "--method", "trailer", "-M", &path("msg")]).unwrap();
+        assert_eq!(fs::read(dir.join("msg")).unwrap(), b"hi");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_key_source() {
+        let dir = temp_dir("key");
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        fs::write(dir.join("a.png"), pixels::tests::testing_png(8, 8, 2, 0).as_bytes()).unwrap();
+        fs::write(dir.join("key"), "open sesame\n").unwrap();
+        // a passphrase on the command line is not a source
+        assert!(deepng(&["encode", "-f", &path("a.png"), "--key", "open sesame", "-m", "hi"]).is_err());
+        let key = format!("file:{}", path("key"));
+        deepng(&["encode", "-f", &path("a.png"), "--key", &key, "-m", "hi"]).unwrap();
+        let content = fs::read(dir.join("a.png")).unwrap();
+        let png = png::Png::try_from(content.as_ref()).unwrap();
+        assert!(png.chunk_by_type(&camouflage::chunk_type_from_key("open sesame").to_string()).is_some());
+        deepng(&["decode", "-f", &path("a.png"), "--key", &key, "-M", &path("msg")]).unwrap();
+        assert_eq!(fs::read(dir.join("msg")).unwrap(), b"hi");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_keygen_paths() {
+        let dir = temp_dir("keygen");
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        deepng(&["keygen", "-o", &path("bob.key")]).unwrap();
+        keys::read_identity(&dir.join("bob.key")).unwrap();
+        keys::recipient_arg(&path("bob.key.pub")).unwrap();
+        assert!(deepng(&["keygen", "-o", &path("alice.pub")]).is_err());
+        assert!(!dir.join("alice.pub").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_decode_wrong_password() {
+        let dir = temp_dir("password");
+        let file = dir.join("a.png");
+        fs::write(&file, pixels::tests::testing_png(8, 8, 2, 0).as_bytes()).unwrap();
+        fs::write(dir.join("right"), "right horse").unwrap();
+        fs::write(dir.join("wrong"), "wrong horse").unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        deepng(&["encode", "-f", &path("a.png"), "-t", "ruSt", "-m", "secret",
+                 "--password", &format!("file:{}", path("right"))]).unwrap();
+        let before = fs::read(&file).unwrap();
+
+        let wrong = format!("file:{}", path("wrong"));
+        assert!(deepng(&["decode", "-f", &path("a.png"), "-t", "ruSt", "--password", &wrong]).is_err());
+        assert_eq!(fs::read(&file).unwrap(), before);
+        assert!(deepng(&["decode", "-f", &path("a.png"), "--auto", "--password", &wrong]).is_err());
+        assert_eq!(fs::read(&file).unwrap(), before);
+
+        deepng(&["decode", "-f", &path("a.png"), "-t", "ruSt", "-M", &path("msg"),
+                 "--password", &format!("file:{}", path("right"))]).unwrap();
+        assert_eq!(fs::read(dir.join("msg")).unwrap(), b"secret");
+        assert_ne!(fs::read(&file).unwrap(), before);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
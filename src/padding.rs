@@ -0,0 +1,153 @@
+//
+// author: xigang zhao
+//
+
+// Padding schemes that hide the exact size of a payload container:
+//
+//     pow2                 round up to a power of two
+//     buckets:1k,16k,256k  round up to the next listed size, and past the
+//                          last one to a multiple of it
+//     random:0-4k          add a random number of bytes in the range
+//
+// Sizes are in bytes, with an optional k or m suffix, and no larger than a
+// chunk can be.
+
+use std::fmt;
+use std::str::FromStr;
+use rand::Rng;
+use crate::capacity::MAX_CHUNK_LENGTH;
+use crate::padding_error::PaddingError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Padding {
+    PowerOfTwo,
+    Buckets(Vec<usize>),
+    Random(usize, usize),
+}
+
+impl FromStr for Padding {
+    type Err = PaddingError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scheme, arg) = s.split_once(':').unwrap_or((s, ""));
+        let padding = match (scheme, arg) {
+            ("pow2", "") => Padding::PowerOfTwo,
+            ("buckets", sizes) if !sizes.is_empty() => {
+                let mut sizes: Vec<usize> = sizes.split(',').map(parse_size).collect::<Result<_, _>>()?;
+                sizes.sort();
+                sizes.dedup();
+                if sizes[0] == 0 {
+                    return Err(PaddingError::InvalidSize("0".to_string()));
+                }
+                Padding::Buckets(sizes)
+            }
+            ("random", range) => {
+                let (min, max) = range.split_once('-').ok_or(PaddingError::InvalidRange(range.to_string()))?;
+                let (min, max) = (parse_size(min)?, parse_size(max)?);
+                if min > max {
+                    return Err(PaddingError::InvalidRange(range.to_string()));
+                }
+                Padding::Random(min, max)
+            }
+            _ => return Err(PaddingError::UnknownScheme(s.to_string())),
+        };
+        return Ok(padding);
+    }
+}
+
+impl fmt::Display for Padding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Padding::PowerOfTwo => write!(f, "pow2"),
+            Padding::Buckets(sizes) => {
+                let sizes: Vec<String> = sizes.iter().map(|s| s.to_string()).collect();
+                write!(f, "buckets:{}", sizes.join(","))
+            }
+            Padding::Random(min, max) => write!(f, "random:{}-{}", min, max),
+        }
+    }
+}
+
+impl Padding {
+    // The padded size for `size` bytes, or None if it overflows.
+    pub fn target(&self, size: usize) -> Option<usize> {
+        return match self {
+            Padding::Random(min, max) => size.checked_add(rand::rng().random_range(*min..=*max)),
+            _ => self.max_target(size),
+        };
+    }
+
+    // The largest padded size `target` can give, for working out capacity.
+    pub fn max_target(&self, size: usize) -> Option<usize> {
+        return match self {
+            Padding::PowerOfTwo => size.checked_next_power_of_two(),
+            Padding::Buckets(sizes) => {
+                let largest = *sizes.last().unwrap();
+                match sizes.iter().find(|&&s| s >= size) {
+                    Some(&s) => Some(s),
+                    None => size.div_ceil(largest).checked_mul(largest),
+                }
+            }
+            Padding::Random(_, max) => size.checked_add(*max),
+        };
+    }
+}
+
+fn parse_size(s: &str) -> Result<usize, PaddingError> {
+    let s = s.trim();
+    let lower = s.to_ascii_lowercase();
+    let (digits, unit) = match lower.strip_suffix('k') {
+        Some(digits) => (digits, 1 << 10),
+        None => match lower.strip_suffix('m') {
+            Some(digits) => (digits, 1 << 20),
+            None => (lower.as_str(), 1),
+        },
+    };
+    let size = digits.parse::<usize>()
+                     .ok()
+                     .and_then(|n| n.checked_mul(unit))
+                     .ok_or(PaddingError::InvalidSize(s.to_string()))?;
+    if size > MAX_CHUNK_LENGTH {
+        return Err(PaddingError::SizeTooLarge(s.to_string()));
+    }
+    return Ok(size);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Padding::from_str("pow2").unwrap(), Padding::PowerOfTwo);
+        assert_eq!(Padding::from_str("buckets:16k,1k,4096").unwrap(), Padding::Buckets(vec![1024, 4096, 16384]));
+        assert_eq!(Padding::from_str("random:0-1M").unwrap(), Padding::Random(0, 1 << 20));
+        for bad in ["pow3", "buckets:", "buckets:1k,x", "buckets:0", "random:5-2", "random:5", "pow2:4",
+                    "buckets:2048m", "random:0-18446744073709551615"] {
+            assert!(Padding::from_str(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_targets() {
+        assert_eq!(Padding::PowerOfTwo.target(1000), Some(1024));
+        assert_eq!(Padding::PowerOfTwo.target(1024), Some(1024));
+        let buckets = Padding::Buckets(vec![1024, 4096]);
+        assert_eq!(buckets.target(10), Some(1024));
+        assert_eq!(buckets.target(1025), Some(4096));
+        assert_eq!(buckets.target(5000), Some(8192));
+        let random = Padding::Random(10, 20);
+        for _ in 0..50 {
+            assert!((110..=120).contains(&random.target(100).unwrap()));
+        }
+        assert_eq!(random.max_target(100), Some(120));
+    }
+
+    #[test]
+    fn test_overflow() {
+        assert_eq!(Padding::PowerOfTwo.target(usize::MAX), None);
+        assert_eq!(Padding::Buckets(vec![2]).target(usize::MAX), None);
+        let random = Padding::from_str("random:1k-2k").unwrap();
+        assert_eq!(random.target(usize::MAX - 10), None);
+        assert_eq!(random.max_target(usize::MAX - 10), None);
+    }
+}
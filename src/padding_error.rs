@@ -0,0 +1,26 @@
+//
+// author: xigang zhao
+//
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum PaddingError {
+    UnknownScheme(String),
+    InvalidSize(String),
+    InvalidRange(String),
+    SizeTooLarge(String),
+}
+
+impl fmt::Display for PaddingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PaddingError::UnknownScheme(s) => write!(f, "\"{}\" is not a padding scheme, expected pow2, buckets:SIZES or random:MIN-MAX", s),
+            PaddingError::InvalidSize(s)   => write!(f, "\"{}\" is not a size in bytes", s),
+            PaddingError::InvalidRange(s)  => write!(f, "\"{}\" is not a range MIN-MAX", s),
+            PaddingError::SizeTooLarge(s)  => write!(f, "\"{}\" is larger than a chunk can be", s),
+        }
+    }
+}
+
+impl std::error::Error for PaddingError {}
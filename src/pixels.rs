@@ -0,0 +1,386 @@
+//
+// author: xigang zhao
+//
+
+// Decoded image samples: IDAT data inflated, unfiltered and de-interlaced,
+// and the reverse for writing pixels back into a Png.
+
+use std::convert::TryFrom;
+use std::str::FromStr;
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::ImageHeader;
+use crate::png::Png;
+use crate::png_error::PngError;
+use crate::{zlib, Result};
+
+// (x start, y start, x step, y step) of the seven Adam7 passes
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4),
+    (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2),
+];
+const IDAT_SIZE: usize = 65536;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pixels {
+    header: ImageHeader,
+    data  : Vec<u8>, // non-interlaced scanlines without filter bytes
+}
+
+impl Pixels {
+    pub fn decode(png: &Png) -> Result<Pixels> {
+        let header = ImageHeader::try_from(png.chunk_by_type("IHDR").ok_or(PngError::InvalidImageHeader)?)?;
+        return Pixels::from_stream(header, &idat_data(png));
+    }
+
+    // Decodes the zlib stream of an image with this header, such as the
+    // joined data of an APNG frame's fdAT chunks.
+    pub fn from_stream(header: ImageHeader, stream: &[u8]) -> Result<Pixels> {
+        let width = header.width() as usize;
+        let height = header.height() as usize;
+        let bits = header.bits_per_pixel();
+        let stride = row_bytes(width, bits);
+        // the IHDR can claim any size: nothing is allocated for more than the IDAT data holds
+        let size = raw_size(width, height, bits, header.interlace() != 0).ok_or(PngError::UnsupportedImage)?;
+        let (raw, _) = zlib::inflate_stream(stream, size)?;
+        if raw.len() < size {
+            return Err(Box::from(PngError::InvalidCompressedData));
+        }
+        if header.interlace() == 0 {
+            let data = unfilter(&raw, width, height, bits)?;
+            return Ok(Pixels { header, data });
+        }
+        let mut data: Vec<u8> = vec![0; stride * height];
+        let mut offset = 0;
+        for (x0, y0, dx, dy) in ADAM7 {
+            let pw = (width + dx - x0 - 1) / dx;
+            let ph = (height + dy - y0 - 1) / dy;
+            if pw == 0 || ph == 0 {
+                continue;
+            }
+            let size = (row_bytes(pw, bits) + 1) * ph;
+            if offset + size > raw.len() {
+                return Err(Box::from(PngError::InvalidCompressedData));
+            }
+            let pass = unfilter(&raw[offset..offset + size], pw, ph, bits)?;
+            offset += size;
+            let pass_stride = row_bytes(pw, bits);
+            for py in 0..ph {
+                for px in 0..pw {
+                    let value = get_bits(&pass[py * pass_stride..], px * bits, bits);
+                    let y = y0 + py * dy;
+                    set_bits(&mut data[y * stride..], (x0 + px * dx) * bits, bits, value);
+                }
+            }
+        }
+        return Ok(Pixels { header, data });
+    }
+
+    pub fn header(&self) -> &ImageHeader {
+        return &self.header;
+    }
+
+    pub fn data(&self) -> &[u8] {
+        return &self.data;
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        return &mut self.data;
+    }
+
+    pub fn stride(&self) -> usize {
+        return row_bytes(self.header.width() as usize, self.header.bits_per_pixel());
+    }
+
+    // All samples of the image in row-major, channel-interleaved order.
+    pub fn samples(&self) -> Vec<u16> {
+        let depth = self.header.bit_depth() as usize;
+        let per_row = self.header.width() as usize * self.header.channels();
+        let stride = self.stride();
+        let mut samples: Vec<u16> = Vec::with_capacity(per_row * self.header.height() as usize);
+        for row in self.data.chunks(stride) {
+            for i in 0..per_row {
+                samples.push(get_bits(row, i * depth, depth) as u16);
+            }
+        }
+        return samples;
+    }
+
+    // Byte offsets of the least significant byte of every sample. Only
+    // meaningful for 8 and 16 bit images.
+    pub fn sample_offsets(&self) -> Vec<usize> {
+        let step = self.header.bit_depth() as usize / 8;
+        if step == 0 {
+            return Vec::new();
+        }
+        return (step - 1..self.data.len()).step_by(step).collect();
+    }
+
+    // Replaces the IDAT chunks of `png` with freshly filtered and compressed
+    // data. The image is always written non-interlaced.
+    pub fn encode(&self, png: &mut Png) -> Result<()> {
+        let ihdr_index = png.chunks().iter()
+                            .position(|ck| &ck.chunk_type().bytes() == b"IHDR")
+                            .ok_or(PngError::InvalidImageHeader)?;
+        let first = png.chunks().iter()
+                       .position(|ck| &ck.chunk_type().bytes() == b"IDAT")
+                       .ok_or(PngError::UnsupportedImage)?;
+        while png.remove_first_chunk("IDAT").is_ok() {}
+        let mut ihdr = png.chunks()[ihdr_index].data().to_vec();
+        ihdr[12] = 0;
+        png.set_chunk(ihdr_index, Chunk::new(ChunkType::from_str("IHDR").unwrap(), ihdr));
+        for (i, part) in self.to_stream().chunks(IDAT_SIZE).enumerate() {
+            png.insert_chunk(first + i, Chunk::new(ChunkType::from_str("IDAT").unwrap(), part.to_vec()));
+        }
+        return Ok(());
+    }
+
+    // The pixels filtered and compressed, non-interlaced.
+    pub fn to_stream(&self) -> Vec<u8> {
+        return zlib::deflate(&filter(&self.data, self.stride(), self.header.bits_per_pixel()));
+    }
+}
+
+pub fn idat_data(png: &Png) -> Vec<u8> {
+    return png.chunks().iter()
+              .filter(|ck| &ck.chunk_type().bytes() == b"IDAT")
+              .flat_map(|ck| ck.data().iter().copied())
+              .collect();
+}
+
+// Size of the inflated IDAT data of an image, filter bytes included, or None
+// if it doesn't fit in memory.
+fn raw_size(width: usize, height: usize, bits: usize, interlaced: bool) -> Option<usize> {
+    let size = |w: usize, h: usize| match (w, h) {
+        (0, _) | (_, 0) => Some(0),
+        _ => w.checked_mul(bits)?.div_ceil(8).checked_add(1)?.checked_mul(h),
+    };
+    if !interlaced {
+        return size(width, height);
+    }
+    return ADAM7.iter().try_fold(0usize, |total, &(x0, y0, dx, dy)| {
+        return total.checked_add(size((width + dx - x0 - 1) / dx, (height + dy - y0 - 1) / dy)?);
+    });
+}
+
+fn row_bytes(width: usize, bits_per_pixel: usize) -> usize {
+    return (width * bits_per_pixel).div_ceil(8);
+}
+
+fn get_bits(row: &[u8], bit: usize, count: usize) -> u32 {
+    if count >= 8 {
+        let start = bit / 8;
+        return row[start..start + count / 8].iter().fold(0u32, |acc, &b| (acc << 8) | b as u32);
+    }
+    let shift = 8 - count - bit % 8;
+    return ((row[bit / 8] >> shift) & ((1u8 << count) - 1)) as u32;
+}
+
+fn set_bits(row: &mut [u8], bit: usize, count: usize, value: u32) {
+    if count >= 8 {
+        let start = bit / 8;
+        for (i, byte) in row[start..start + count / 8].iter_mut().rev().enumerate() {
+            *byte = (value >> (8 * i)) as u8;
+        }
+        return;
+    }
+    let shift = 8 - count - bit % 8;
+    let mask = ((1u8 << count) - 1) << shift;
+    row[bit / 8] = (row[bit / 8] & !mask) | (((value as u8) << shift) & mask);
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        return a;
+    }
+    return if pb <= pc { b } else { c };
+}
+
+fn unfilter(raw: &[u8], width: usize, height: usize, bits: usize) -> Result<Vec<u8>> {
+    let stride = row_bytes(width, bits);
+    let bpp = bits.div_ceil(8);
+    if raw.len() < (stride + 1) * height {
+        return Err(Box::from(PngError::InvalidCompressedData));
+    }
+    let mut data: Vec<u8> = vec![0; stride * height];
+    for y in 0..height {
+        let line = &raw[y * (stride + 1)..(y + 1) * (stride + 1)];
+        let (prev, cur) = data.split_at_mut(y * stride);
+        let prev = if y == 0 { None } else { Some(&prev[(y - 1) * stride..]) };
+        let cur = &mut cur[..stride];
+        for x in 0..stride {
+            let a = if x >= bpp { cur[x - bpp] } else { 0 };
+            let b = prev.map_or(0, |p| p[x]);
+            let c = if x >= bpp { prev.map_or(0, |p| p[x - bpp]) } else { 0 };
+            let predictor = match line[0] {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(Box::from(PngError::InvalidCompressedData)),
+            };
+            cur[x] = line[x + 1].wrapping_add(predictor);
+        }
+    }
+    return Ok(data);
+}
+
+// Filters every row with whichever filter gives the smallest sum of absolute
+// values, the usual heuristic from the PNG spec.
+fn filter(data: &[u8], stride: usize, bits: usize) -> Vec<u8> {
+    let bpp = bits.div_ceil(8);
+    let mut raw: Vec<u8> = Vec::with_capacity(data.len() + data.len() / stride.max(1));
+    let mut candidate: Vec<u8> = vec![0; stride];
+    let mut best: Vec<u8> = vec![0; stride];
+    for (y, cur) in data.chunks(stride).enumerate() {
+        let prev = if y == 0 { None } else { Some(&data[(y - 1) * stride..y * stride]) };
+        let mut best_type = 0u8;
+        let mut best_score = u64::MAX;
+        for filter_type in 0..5u8 {
+            for x in 0..stride {
+                let a = if x >= bpp { cur[x - bpp] } else { 0 };
+                let b = prev.map_or(0, |p| p[x]);
+                let c = if x >= bpp { prev.map_or(0, |p| p[x - bpp]) } else { 0 };
+                let predictor = match filter_type {
+                    0 => 0,
+                    1 => a,
+                    2 => b,
+                    3 => ((a as u16 + b as u16) / 2) as u8,
+                    _ => paeth(a, b, c),
+                };
+                candidate[x] = cur[x].wrapping_sub(predictor);
+            }
+            let score: u64 = candidate.iter().map(|&v| (v as i8).unsigned_abs() as u64).sum();
+            if score < best_score {
+                best_score = score;
+                best_type = filter_type;
+                best.copy_from_slice(&candidate);
+            }
+        }
+        raw.push(best_type);
+        raw.extend_from_slice(&best);
+    }
+    return raw;
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn chunk(chunk_type: &str, data: Vec<u8>) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+    }
+
+    // A `width` x `height` image with a smooth gradient, written with filter
+    // type `filter_type` on every row.
+    pub fn testing_png(width: u32, height: u32, color_type: u8, filter_type: u8) -> Png {
+        let mut ihdr = width.to_be_bytes().to_vec();
+        ihdr.extend_from_slice(&height.to_be_bytes());
+        ihdr.extend_from_slice(&[8, color_type, 0, 0, 0]);
+        let header = ImageHeader::try_from(&chunk("IHDR", ihdr.clone())).unwrap();
+        let stride = width as usize * header.channels();
+        let mut raw: Vec<u8> = Vec::new();
+        let mut prev: Vec<u8> = vec![0; stride];
+        for y in 0..height as usize {
+            let row: Vec<u8> = (0..stride).map(|x| ((x * 3 + y * 5) % 256) as u8).collect();
+            raw.push(filter_type);
+            for x in 0..stride {
+                let a = if x >= header.channels() { row[x - header.channels()] } else { 0 };
+                let predictor = match filter_type {
+                    1 => a,
+                    2 => prev[x],
+                    _ => 0,
+                };
+                raw.push(row[x].wrapping_sub(predictor));
+            }
+            prev = row;
+        }
+        Png::from_chunks(vec![
+            chunk("IHDR", ihdr),
+            chunk("IDAT", zlib::deflate(&raw)),
+            chunk("IEND", vec![]),
+        ])
+    }
+
+    #[test]
+    fn test_decode_filters() {
+        let expected = Pixels::decode(&testing_png(16, 8, 2, 0)).unwrap();
+        // row 2, byte 4 of a 48-byte stride
+        assert_eq!(expected.data()[2 * 48 + 4], (4 * 3 + 2 * 5) as u8);
+        for filter_type in 1..3 {
+            let actual = Pixels::decode(&testing_png(16, 8, 2, filter_type)).unwrap();
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        let mut png = testing_png(33, 17, 6, 0);
+        let pixels = Pixels::decode(&png).unwrap();
+        pixels.encode(&mut png).unwrap();
+        assert_eq!(Pixels::decode(&png).unwrap(), pixels);
+        assert_eq!(png.chunks()[1].chunk_type().to_string(), "IDAT");
+    }
+
+    #[test]
+    fn test_decode_interlaced() {
+        let png = testing_png(13, 11, 2, 0);
+        let expected = Pixels::decode(&png).unwrap();
+        let mut raw: Vec<u8> = Vec::new();
+        for (x0, y0, dx, dy) in ADAM7 {
+            for y in (y0..11).step_by(dy) {
+                let row: Vec<u8> = (x0..13).step_by(dx)
+                                           .flat_map(|x| expected.data()[y * 39 + x * 3..y * 39 + x * 3 + 3].to_vec())
+                                           .collect();
+                if !row.is_empty() {
+                    raw.push(0);
+                    raw.extend_from_slice(&row);
+                }
+            }
+        }
+        let mut ihdr = png.chunks()[0].data().to_vec();
+        ihdr[12] = 1;
+        let interlaced = Png::from_chunks(vec![
+            chunk("IHDR", ihdr),
+            chunk("IDAT", zlib::deflate(&raw)),
+            chunk("IEND", vec![]),
+        ]);
+        assert_eq!(Pixels::decode(&interlaced).unwrap().data(), expected.data());
+    }
+
+    #[test]
+    fn test_huge_header() {
+        for interlace in [0, 1] {
+            let mut ihdr = 0x7fff_ffffu32.to_be_bytes().repeat(2);
+            ihdr.extend_from_slice(&[8, 6, 0, 0, interlace]);
+            let png = Png::from_chunks(vec![
+                chunk("IHDR", ihdr),
+                chunk("IDAT", zlib::deflate(&[0; 64])),
+                chunk("IEND", vec![]),
+            ]);
+            assert!(Pixels::decode(&png).is_err());
+        }
+    }
+
+    #[test]
+    fn test_samples() {
+        let pixels = Pixels::decode(&testing_png(4, 2, 0, 0)).unwrap();
+        assert_eq!(pixels.samples(), vec![0, 3, 6, 9, 5, 8, 11, 14]);
+        assert_eq!(pixels.sample_offsets().len(), 8);
+    }
+
+    #[test]
+    fn test_bits() {
+        let mut row = vec![0u8; 2];
+        set_bits(&mut row, 2, 2, 3);
+        set_bits(&mut row, 12, 4, 9);
+        assert_eq!(row, vec![0b0011_0000, 0b0000_1001]);
+        assert_eq!(get_bits(&row, 2, 2), 3);
+        assert_eq!(get_bits(&row, 12, 4), 9);
+    }
+}
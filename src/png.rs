@@ -6,10 +6,12 @@ use std::convert::TryFrom;
 use std::fmt;
 use crate::{chunk::Chunk, Error, Result};
 use crate::png_error::PngError;
+use crate::text_chunk::TextChunk;
 
 pub struct Png {
     header   : [u8; Png::HEADER_SIZE],
     chunklist: Vec<Chunk>,
+    trailer  : Vec<u8>, // bytes after IEND that don't form chunks
 }
 
 impl TryFrom<&[u8]> for Png {
@@ -24,13 +26,21 @@ impl TryFrom<&[u8]> for Png {
         }
         let header: [u8; Png::HEADER_SIZE] = header.try_into().unwrap();
         let mut chunklist: Vec<Chunk> = Vec::new();
+        let mut seen_iend = false;
         let mut i = 0;
         while i < value.len() {
-            let ck = Chunk::try_from(&value[i..])?;
-            i += (ck.length() as usize + Chunk::CHUNK_MINIMUM);
+            // past IEND, anything that isn't a well-formed chunk is trailing data
+            let ck = match Chunk::try_from(&value[i..]) {
+                Ok(ck) => ck,
+                Err(_) if seen_iend => break,
+                Err(e) => return Err(Box::from(e)),
+            };
+            i += ck.length() as usize + Chunk::CHUNK_MINIMUM;
+            seen_iend |= &ck.chunk_type().bytes() == b"IEND";
             chunklist.push(ck);
         }
-        return Ok(Png {header: header, chunklist: chunklist });
+        let trailer = value[i..].to_vec();
+        return Ok(Png {header: header, chunklist: chunklist, trailer: trailer });
     }
 }
 
@@ -40,10 +50,13 @@ impl fmt::Display for Png {
         for byte in &self.header {
             write!(f, "{} ", byte)?;
         }
-        writeln!(f, "")?;
+        writeln!(f)?;
         for (i, ck) in self.chunks().iter().enumerate() {
             writeln!(f, "chunk #{} :", i)?;
-            writeln!(f, "{}", ck.to_string())?;
+            writeln!(f, "{}", ck)?;
+        }
+        if !self.trailer.is_empty() {
+            writeln!(f, "trailer: {} bytes", self.trailer.len())?;
         }
         write!(f, "done!")
     }
@@ -55,16 +68,28 @@ impl Png {
 
     pub fn from_chunks(chunks: Vec<Chunk>) -> Png {
         let header = Png::STANDARD_HEADER;
-        return Png { header, chunklist: chunks };
+        return Png { header, chunklist: chunks, trailer: Vec::new() };
     }
 
     pub fn append_chunk(&mut self, chunk: Chunk) {
         self.chunklist.push(chunk);
     }
 
+    pub fn insert_chunk(&mut self, index: usize, chunk: Chunk) {
+        self.chunklist.insert(index, chunk);
+    }
+
+    pub fn set_chunk(&mut self, index: usize, chunk: Chunk) {
+        self.chunklist[index] = chunk;
+    }
+
+    pub fn remove_chunk(&mut self, index: usize) -> Chunk {
+        return self.chunklist.remove(index);
+    }
+
     pub fn remove_first_chunk(&mut self, chunktype: &str) -> Result<Chunk> {
         for i in 0..self.chunklist.len() {
-            if &self.chunklist[i].chunk_type().to_string() == chunktype {
+            if self.chunklist[i].chunk_type().to_string() == chunktype {
                 return Ok(self.chunklist.remove(i));
             }
         }
@@ -73,13 +98,32 @@ impl Png {
 
     pub fn remove_last_chunk(&mut self, chunktype: &str) -> Result<Chunk> {
         for i in (0..self.chunklist.len()).rev() {
-            if &self.chunklist[i].chunk_type().to_string() == chunktype {
+            if self.chunklist[i].chunk_type().to_string() == chunktype {
                 return Ok(self.chunklist.remove(i));
             }
         }
         return Err(Box::from(PngError::UnknownChunkType));
     }
 
+    // Removes the last text chunk stored under `keyword` that `decode`
+    // accepts; chunks it rejects stay where they are.
+    pub fn remove_last_text_chunk<T>(&mut self, keyword: &str, decode: impl Fn(&TextChunk) -> Result<T>) -> Result<T> {
+        let mut error: Option<Error> = None;
+        for i in (0..self.chunklist.len()).rev() {
+            if let Ok(tc) = TextChunk::try_from(&self.chunklist[i])
+                && tc.keyword() == keyword {
+                match decode(&tc) {
+                    Ok(value) => {
+                        self.chunklist.remove(i);
+                        return Ok(value);
+                    }
+                    Err(e) => { error.get_or_insert(e); }
+                }
+            }
+        }
+        return Err(error.unwrap_or(Box::from(PngError::UnknownKeyword)));
+    }
+
     pub fn header(&self) -> &[u8; Png::HEADER_SIZE] {
         return &self.header;
     }
@@ -88,13 +132,20 @@ impl Png {
         return &self.chunklist;
     }
 
+    pub fn trailer(&self) -> &[u8] {
+        return &self.trailer;
+    }
+
+    pub fn set_trailer(&mut self, trailer: Vec<u8>) {
+        self.trailer = trailer;
+    }
+
+    pub fn take_trailer(&mut self) -> Vec<u8> {
+        return std::mem::take(&mut self.trailer);
+    }
+
     pub fn chunk_by_type(&self, chunktype: &str) -> Option<&Chunk> {
-        for ck in &self.chunklist {
-            if ck.chunk_type().to_string() == chunktype {
-                return Some(ck);
-            }
-        }
-        return None;
+        return self.chunklist.iter().find(|ck| ck.chunk_type().to_string() == chunktype);
     }
 
     pub fn as_bytes(&self) -> Vec<u8> {
@@ -105,6 +156,7 @@ impl Png {
                                             //    .collect();
         let raw_bytes: Vec<u8> = self.header.iter()
                                             .chain(data_vec.iter())
+                                            .chain(self.trailer.iter())
                                             .copied()
                                             .collect();
         return raw_bytes;
@@ -251,6 +303,61 @@ mod tests {
         assert!(chunk.is_none());
     }
 
+    #[test]
+    fn test_insert_chunk() {
+        let mut png = testing_png();
+        png.insert_chunk(1, chunk_from_strings("TeSt", "Message").unwrap());
+        assert_eq!(png.chunks().len(), 4);
+        assert_eq!(png.chunks()[1].chunk_type().to_string(), "TeSt");
+    }
+
+    #[test]
+    fn test_remove_last_text_chunk() {
+        use crate::text_chunk::TextKind;
+        let mut png = testing_png();
+        let tc = TextChunk::new(TextKind::International, "Comment", "first").unwrap();
+        png.append_chunk(tc.to_chunk());
+        let tc = TextChunk::new(TextKind::Compressed, "Comment", "second").unwrap();
+        png.append_chunk(tc.to_chunk());
+        let text = |tc: &TextChunk| Ok(tc.text().to_string());
+        assert_eq!(png.remove_last_text_chunk("Comment", text).unwrap(), "second");
+        assert_eq!(png.remove_last_text_chunk("Comment", text).unwrap(), "first");
+        assert!(png.remove_last_text_chunk("Comment", text).is_err());
+    }
+
+    #[test]
+    fn test_remove_last_text_chunk_rejected() {
+        use crate::text_chunk::TextKind;
+        let mut png = testing_png();
+        png.append_chunk(TextChunk::new(TextKind::Text, "Comment", "payload").unwrap().to_chunk());
+        png.append_chunk(TextChunk::new(TextKind::Text, "Comment", "a real comment").unwrap().to_chunk());
+        let payload = |tc: &TextChunk| match tc.text() {
+            "payload" => Ok(()),
+            _ => Err(Box::from(PngError::InvalidCoverText)),
+        };
+        png.remove_last_text_chunk("Comment", payload).unwrap();
+        assert!(png.remove_last_text_chunk("Comment", payload).is_err());
+        assert_eq!(png.chunks().len(), 4);
+    }
+
+    #[test]
+    fn test_trailer_round_trip() {
+        let mut bytes: Vec<u8> = PNG_FILE.to_vec();
+        bytes.extend_from_slice(b"appended after IEND");
+        let png = Png::try_from(bytes.as_ref()).unwrap();
+        assert_eq!(png.trailer(), b"appended after IEND");
+        assert_eq!(png.as_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_chunks_after_iend() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.append_chunk(chunk_from_strings("ruSt", "Message").unwrap());
+        let png = Png::try_from(png.as_bytes().as_ref()).unwrap();
+        assert!(png.trailer().is_empty());
+        assert!(png.chunk_by_type("ruSt").is_some());
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
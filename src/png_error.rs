@@ -16,6 +16,20 @@ pub enum PngError {
     InvalidCRC,
     InvalidChunk(ChunkError),
     UnknownChunkType,
+    InvalidTextChunk,
+    InvalidKeyword,
+    InvalidCoverText,
+    UnknownKeyword,
+    MissingChunkType,
+    InvalidImageHeader,
+    NotAnimated,
+    InsufficientCapacity(usize, usize),
+    InvalidPayload,
+    InvalidCompressedData,
+    InflatedTooLarge(usize),
+    UnsupportedImage,
+    UnknownPayloadName(String),
+    TrailerInUse(usize),
 }
 
 impl fmt::Display for PngError {
@@ -29,6 +43,22 @@ impl fmt::Display for PngError {
             PngError::InvalidChunk(ce) => write!(f, "Invalid chunk: {}", ce),
             PngError::InvalidCRC       => write!(f, "Invalid CRC in input"),
             PngError::UnknownChunkType => write!(f, "Unknown chunk type"),
+            PngError::InvalidTextChunk => write!(f, "Invalid text chunk"),
+            PngError::InvalidKeyword   => write!(f, "Invalid text keyword"),
+            PngError::InvalidCoverText => write!(f, "Text does not hold an encoded message"),
+            PngError::UnknownKeyword   => write!(f, "No text chunk with that keyword"),
+            PngError::MissingChunkType => write!(f, "A chunk type (--type or --key) is required for this method"),
+            PngError::InvalidImageHeader => write!(f, "Invalid IHDR chunk"),
+            PngError::NotAnimated      => write!(f, "Not an animated PNG"),
+            PngError::InsufficientCapacity(needed, available) =>
+                write!(f, "Message needs {} bytes but the carrier holds only {}", needed, available),
+            PngError::InvalidPayload   => write!(f, "No valid payload found"),
+            PngError::InvalidCompressedData => write!(f, "Invalid zlib data"),
+            PngError::InflatedTooLarge(limit) => write!(f, "zlib data inflates to more than {} bytes", limit),
+            PngError::UnsupportedImage => write!(f, "Unsupported image format"),
+            PngError::UnknownPayloadName(name) => write!(f, "No payload named \"{}\"", name),
+            PngError::TrailerInUse(size) =>
+                write!(f, "The file already has {} bytes of trailing data, use --replace to overwrite them", size),
         }
     }
 }
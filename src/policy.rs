@@ -0,0 +1,226 @@
+//
+// author: xigang zhao
+//
+
+// Chunk allowlists and denylists. A policy file has one rule per line:
+//
+//     # consumers B and C
+//     allow tEXt
+//     deny private unsafe-to-copy
+//     deny ancillary
+//     default allow
+//
+// A rule matches when every word after the action matches: either an exact
+// chunk type or one of the ChunkType properties. The first matching rule
+// decides; chunks that no rule matches get the default, which is allow.
+
+use std::fmt;
+use std::str::FromStr;
+use crate::chunk_type::ChunkType;
+use crate::policy_error::PolicyError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Allow,
+    Deny,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pattern {
+    Any,
+    Type(ChunkType),
+    Critical,
+    Ancillary,
+    Public,
+    Private,
+    Reserved,
+    SafeToCopy,
+    UnsafeToCopy,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    action  : Action,
+    patterns: Vec<Pattern>,
+    line    : usize,
+    text    : String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Policy {
+    rules  : Vec<Rule>,
+    default: Action,
+}
+
+impl FromStr for Action {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow" => Ok(Action::Allow),
+            "deny"  => Ok(Action::Deny),
+            _       => Err(()),
+        }
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let pattern = match s {
+            "*" | "all"      => Pattern::Any,
+            "critical"       => Pattern::Critical,
+            "ancillary"      => Pattern::Ancillary,
+            "public"         => Pattern::Public,
+            "private"        => Pattern::Private,
+            "reserved"       => Pattern::Reserved,
+            "safe-to-copy"   => Pattern::SafeToCopy,
+            "unsafe-to-copy" => Pattern::UnsafeToCopy,
+            _ => Pattern::Type(ChunkType::from_str(s).map_err(|_| ())?),
+        };
+        return Ok(pattern);
+    }
+}
+
+impl Pattern {
+    pub fn matches(&self, chunk_type: &ChunkType) -> bool {
+        return match self {
+            Pattern::Any          => true,
+            Pattern::Type(t)      => t == chunk_type,
+            Pattern::Critical     => chunk_type.is_critical(),
+            Pattern::Ancillary    => !chunk_type.is_critical(),
+            Pattern::Public       => chunk_type.is_public(),
+            Pattern::Private      => !chunk_type.is_public(),
+            // the reserved bit must be 0 (uppercase) in every valid chunk type
+            Pattern::Reserved     => !chunk_type.is_reserved_bit_valid(),
+            Pattern::SafeToCopy   => chunk_type.is_safe_to_copy(),
+            Pattern::UnsafeToCopy => !chunk_type.is_safe_to_copy(),
+        };
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.text)
+    }
+}
+
+impl Rule {
+    pub fn matches(&self, chunk_type: &ChunkType) -> bool {
+        return self.patterns.iter().all(|p| p.matches(chunk_type));
+    }
+}
+
+impl FromStr for Policy {
+    type Err = PolicyError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rules: Vec<Rule> = Vec::new();
+        let mut default = Action::Allow;
+        for (n, line) in s.lines().enumerate() {
+            let text = line.split('#').next().unwrap().trim();
+            let mut words = text.split_whitespace();
+            let first = match words.next() {
+                Some(word) => word,
+                None => continue,
+            };
+            if first == "default" {
+                default = match (words.next().map(Action::from_str), words.next()) {
+                    (Some(Ok(action)), None) => action,
+                    _ => return Err(PolicyError::InvalidDefault(n + 1)),
+                };
+                continue;
+            }
+            let action = Action::from_str(first).map_err(|_| PolicyError::InvalidAction(n + 1, first.to_string()))?;
+            let patterns = words.map(|w| Pattern::from_str(w).map_err(|_| PolicyError::InvalidPattern(n + 1, w.to_string())))
+                                .collect::<Result<Vec<Pattern>, PolicyError>>()?;
+            if patterns.is_empty() {
+                return Err(PolicyError::MissingPattern(n + 1));
+            }
+            rules.push(Rule { action, patterns, line: n + 1, text: text.to_string() });
+        }
+        return Ok(Policy { rules, default });
+    }
+}
+
+impl Policy {
+    // Denies exactly the listed chunk types.
+    pub fn deny(chunk_types: &[ChunkType]) -> Policy {
+        let rules = chunk_types.iter().map(|t| Rule {
+            action  : Action::Deny,
+            patterns: vec![Pattern::Type(*t)],
+            line    : 0,
+            text    : format!("deny {}", t),
+        }).collect();
+        return Policy { rules, default: Action::Allow };
+    }
+
+    // The deciding rule, or None when the default applies.
+    pub fn rule_for(&self, chunk_type: &ChunkType) -> Option<&Rule> {
+        return self.rules.iter().find(|r| r.matches(chunk_type));
+    }
+
+    pub fn action(&self, chunk_type: &ChunkType) -> Action {
+        return self.rule_for(chunk_type).map_or(self.default, |r| r.action);
+    }
+
+    pub fn allows(&self, chunk_type: &ChunkType) -> bool {
+        return self.action(chunk_type) == Action::Allow;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ct(s: &str) -> ChunkType {
+        ChunkType::from_str(s).unwrap()
+    }
+
+    const POLICY: &str = "\
+# consumers B and C
+allow tEXt
+deny private unsafe-to-copy   # e.g. ruST
+deny ancillary public
+";
+
+    #[test]
+    fn test_parse() {
+        let policy = Policy::from_str(POLICY).unwrap();
+        assert_eq!(policy.rules.len(), 3);
+        assert_eq!(policy.rules[1].patterns, [Pattern::Private, Pattern::UnsafeToCopy]);
+        assert_eq!(policy.rules[1].to_string(), "line 3: deny private unsafe-to-copy");
+        assert_eq!(policy.default, Action::Allow);
+    }
+
+    #[test]
+    fn test_first_match_wins() {
+        let policy = Policy::from_str(POLICY).unwrap();
+        assert!(policy.allows(&ct("tEXt")));
+        assert!(!policy.allows(&ct("zTXt")));
+        assert!(!policy.allows(&ct("ruST")));
+        assert!(policy.allows(&ct("ruSt")));
+        assert!(policy.allows(&ct("IDAT")));
+        assert_eq!(policy.rule_for(&ct("ruST")).unwrap().line, 3);
+        assert!(policy.rule_for(&ct("ruSt")).is_none());
+    }
+
+    #[test]
+    fn test_default() {
+        let policy = Policy::from_str("allow critical\ndefault deny").unwrap();
+        assert!(policy.allows(&ct("PLTE")));
+        assert!(!policy.allows(&ct("gAMA")));
+    }
+
+    #[test]
+    fn test_reserved() {
+        assert!(Pattern::Reserved.matches(&ct("ruse")));
+        assert!(!Pattern::Reserved.matches(&ct("ruSe")));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(matches!(Policy::from_str("keep tEXt"), Err(PolicyError::InvalidAction(1, _))));
+        assert!(matches!(Policy::from_str("\nallow texts"), Err(PolicyError::InvalidPattern(2, _))));
+        assert!(matches!(Policy::from_str("deny"), Err(PolicyError::MissingPattern(1))));
+        assert!(matches!(Policy::from_str("default maybe"), Err(PolicyError::InvalidDefault(1))));
+    }
+}
@@ -0,0 +1,26 @@
+//
+// author: xigang zhao
+//
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum PolicyError {
+    InvalidAction(usize, String),
+    InvalidPattern(usize, String),
+    MissingPattern(usize),
+    InvalidDefault(usize),
+}
+
+impl fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PolicyError::InvalidAction(line, word)  => write!(f, "Line {}: expected allow, deny or default, found \"{}\"", line, word),
+            PolicyError::InvalidPattern(line, word) => write!(f, "Line {}: \"{}\" is neither a chunk type nor a chunk property", line, word),
+            PolicyError::MissingPattern(line)       => write!(f, "Line {}: rule has no pattern", line),
+            PolicyError::InvalidDefault(line)       => write!(f, "Line {}: default must be followed by allow or deny", line),
+        }
+    }
+}
+
+impl std::error::Error for PolicyError {}
@@ -0,0 +1,274 @@
+//
+// author: xigang zhao
+//
+
+// Steganalysis of a parsed PNG: looks for places where data is commonly
+// hidden and scores each finding from 0 (harmless) to 100 (almost certain).
+
+use std::convert::TryFrom;
+use std::fmt;
+use crate::chunk::Chunk;
+use crate::pixels::{self, Pixels};
+use crate::png::Png;
+use crate::text_chunk::TextChunk;
+use crate::zlib;
+
+pub const KNOWN_CHUNK_TYPES: [&str; 25] = [
+    "IHDR", "PLTE", "IDAT", "IEND",
+    "cHRM", "gAMA", "iCCP", "sBIT", "sRGB", "cICP", "mDCV", "cLLI",
+    "bKGD", "hIST", "tRNS", "eXIf", "pHYs", "sPLT", "tIME",
+    "iTXt", "tEXt", "zTXt", "acTL", "fcTL", "fdAT",
+];
+
+pub const LARGE_TEXT: usize = 1024;
+const TEXT_ENTROPY: f64 = 5.0;
+const MIN_ENTROPY_LEN: usize = 64;
+pub const LSB_MIN_SAMPLES: usize = 4096;
+const LSB_PAIR_BALANCE: f64 = 0.02;
+
+#[derive(Debug, PartialEq)]
+pub struct ScanFinding {
+    score  : u32,
+    message: String,
+}
+
+#[derive(Debug, Default)]
+pub struct ScanReport {
+    findings: Vec<ScanFinding>,
+}
+
+impl fmt::Display for ScanFinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{:>3}] {}", self.score, self.message)
+    }
+}
+
+impl ScanFinding {
+    pub fn new(score: u32, message: String) -> ScanFinding {
+        return ScanFinding { score: score.min(100), message };
+    }
+}
+
+impl ScanReport {
+    pub fn findings(&self) -> &[ScanFinding] {
+        return &self.findings;
+    }
+
+    pub fn push(&mut self, score: u32, message: String) {
+        self.findings.push(ScanFinding::new(score, message));
+    }
+
+    // Combines the findings as independent pieces of evidence.
+    pub fn score(&self) -> u32 {
+        let clean: f64 = self.findings.iter().map(|f| 1.0 - f.score as f64 / 100.0).product();
+        return ((1.0 - clean) * 100.0).round() as u32;
+    }
+}
+
+pub fn scan(png: &Png) -> ScanReport {
+    let mut report = ScanReport::default();
+    let mut after_iend = false;
+    for ck in png.chunks() {
+        let name = ck.chunk_type().to_string();
+        if after_iend {
+            report.push(70, format!("{} chunk after IEND ({} bytes)", name, ck.length()));
+        }
+        after_iend |= name == "IEND";
+        scan_chunk_type(ck, &mut report);
+        scan_text(ck, &mut report);
+    }
+    if !png.trailer().is_empty() {
+        report.push(80, format!("{} bytes of data after IEND", png.trailer().len()));
+    }
+    let idat = pixels::idat_data(png);
+    if !idat.is_empty() && let Ok((_, used)) = zlib::inflate_stream(&idat, zlib::MAX_INFLATED) && used < idat.len() {
+        report.push(80, format!("{} extra bytes after the IDAT zlib stream", idat.len() - used));
+    }
+    if let Ok(pixels) = Pixels::decode(png) {
+        scan_lsb(&pixels, &mut report);
+    }
+    return report;
+}
+
+fn scan_chunk_type(ck: &Chunk, report: &mut ScanReport) {
+    let ct = ck.chunk_type();
+    if KNOWN_CHUNK_TYPES.contains(&ct.to_string().as_str()) {
+        return;
+    }
+    let what = match (ct.is_critical(), ct.is_public()) {
+        (true, _)      => ("unknown critical", 40),
+        (false, false) => ("private ancillary", 60),
+        (false, true)  => ("unknown public ancillary", 30),
+    };
+    // private, reserved-bit valid and safe to copy: the pattern deepng itself uses
+    let score = if ct.is_valid_type() { what.1 + 15 } else { what.1 };
+    report.push(score, format!("{} chunk {} ({} bytes)", what.0, ct, ck.length()));
+}
+
+fn scan_text(ck: &Chunk, report: &mut ScanReport) {
+    let name = ck.chunk_type().to_string();
+    if let Some(stream) = compressed_stream(&name, ck.data())
+        && let Ok((_, used)) = zlib::inflate_stream(stream, zlib::MAX_INFLATED) && used < stream.len() {
+        report.push(70, format!("{} extra bytes after the zlib stream in {}", stream.len() - used, name));
+    }
+    let tc = match TextChunk::try_from(ck) {
+        Ok(tc) => tc,
+        Err(_) => return,
+    };
+    let text = tc.text().as_bytes();
+    if text.len() > LARGE_TEXT {
+        report.push(40, format!("large {} chunk \"{}\" ({} bytes of text)", name, tc.keyword(), text.len()));
+    }
+    let entropy = shannon_entropy(text);
+    if text.len() >= MIN_ENTROPY_LEN && entropy > TEXT_ENTROPY {
+        report.push(50, format!("high-entropy {} chunk \"{}\" ({:.2} bits per byte)", name, tc.keyword(), entropy));
+    }
+}
+
+// The zlib stream inside a zTXt, iCCP or compressed iTXt chunk.
+fn compressed_stream<'a>(name: &str, data: &'a [u8]) -> Option<&'a [u8]> {
+    let nul = data.iter().position(|&b| b == 0)?;
+    match name {
+        // keyword, compression method, stream
+        "zTXt" | "iCCP" => data.get(nul + 2..),
+        // keyword, flag, method, language tag, translated keyword, text
+        "iTXt" if data.get(nul + 1) == Some(&1) => {
+            let rest = data.get(nul + 3..)?;
+            let lang = rest.iter().position(|&b| b == 0)?;
+            let translated = rest[lang + 1..].iter().position(|&b| b == 0)?;
+            rest.get(lang + translated + 2..)
+        }
+        _ => None,
+    }
+}
+
+// LSB replacement evens out the counts of each pair of values 2k and 2k+1.
+// Natural images rarely have pairs that are this well balanced.
+fn scan_lsb(pixels: &Pixels, report: &mut ScanReport) {
+    let header = pixels.header();
+    if header.color_type() == 3 || header.bit_depth() < 8 {
+        return;
+    }
+    let channels = header.channels();
+    let samples = pixels.samples();
+    if samples.len() / channels < LSB_MIN_SAMPLES {
+        return;
+    }
+    for c in 0..channels {
+        let mut histogram = [0u64; 256];
+        for s in samples.iter().skip(c).step_by(channels) {
+            // the low byte holds the LSB for 16-bit samples
+            histogram[(*s & 0xff) as usize] += 1;
+        }
+        let balance = pair_balance(&histogram);
+        if balance < LSB_PAIR_BALANCE {
+            report.push(50, format!("channel {} LSB pairs are unusually balanced ({:.4})", c, balance));
+        }
+    }
+}
+
+pub fn pair_balance(histogram: &[u64; 256]) -> f64 {
+    let mut diff = 0u64;
+    let mut total = 0u64;
+    for k in 0..128 {
+        let (even, odd) = (histogram[2 * k], histogram[2 * k + 1]);
+        if even + odd >= 10 {
+            diff += even.abs_diff(odd);
+            total += even + odd;
+        }
+    }
+    return if total == 0 { 1.0 } else { diff as f64 / total as f64 };
+}
+
+pub fn shannon_entropy(data: &[u8]) -> f64 {
+    if data.is_empty() {
+        return 0.0;
+    }
+    let mut counts = [0usize; 256];
+    for &b in data {
+        counts[b as usize] += 1;
+    }
+    let n = data.len() as f64;
+    return counts.iter()
+                 .filter(|&&c| c > 0)
+                 .map(|&c| { let p = c as f64 / n; -p * p.log2() })
+                 .sum();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::chunk_type::ChunkType;
+    use crate::pixels::tests::testing_png;
+    use crate::text_chunk::TextKind;
+
+    fn chunk(chunk_type: &str, data: Vec<u8>) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+    }
+
+    fn insert(png: &mut Png, ck: Chunk) {
+        png.insert_chunk(png.chunks().len() - 1, ck);
+    }
+
+    #[test]
+    fn test_clean_file() {
+        let report = scan(&testing_png(16, 16, 2, 0));
+        assert!(report.findings().is_empty());
+        assert_eq!(report.score(), 0);
+    }
+
+    #[test]
+    fn test_private_chunk() {
+        let mut png = testing_png(16, 16, 2, 0);
+        insert(&mut png, chunk("ruSt", b"hidden".to_vec()));
+        let report = scan(&png);
+        assert_eq!(report.findings().len(), 1);
+        assert_eq!(report.findings()[0].score, 75);
+    }
+
+    #[test]
+    fn test_trailer_and_after_iend() {
+        let mut png = testing_png(16, 16, 2, 0);
+        png.append_chunk(chunk("tEXt", b"Comment\0hi".to_vec()));
+        png.set_trailer(b"more".to_vec());
+        let report = scan(&png);
+        assert_eq!(report.findings().len(), 2);
+        assert!(report.score() > 90);
+    }
+
+    #[test]
+    fn test_high_entropy_text() {
+        let mut png = testing_png(16, 16, 2, 0);
+        let text: String = (0..2000u32).map(|i| (33 + (i * 7919 % 94)) as u8 as char).collect();
+        insert(&mut png, TextChunk::new(TextKind::Text, "Comment", &text).unwrap().to_chunk());
+        let report = scan(&png);
+        assert_eq!(report.findings().len(), 2);
+    }
+
+    #[test]
+    fn test_ztxt_extra_bytes() {
+        let mut png = testing_png(16, 16, 2, 0);
+        let mut data = b"Comment\0\0".to_vec();
+        data.extend_from_slice(&zlib::deflate(b"hello"));
+        data.extend_from_slice(b"smuggled");
+        insert(&mut png, chunk("zTXt", data));
+        assert_eq!(scan(&png).findings()[0].message, "8 extra bytes after the zlib stream in zTXt");
+    }
+
+    #[test]
+    fn test_pair_balance() {
+        let mut histogram = [0u64; 256];
+        histogram[10] = 100;
+        histogram[11] = 100;
+        assert_eq!(pair_balance(&histogram), 0.0);
+        histogram[11] = 0;
+        assert_eq!(pair_balance(&histogram), 1.0);
+    }
+
+    #[test]
+    fn test_entropy() {
+        assert_eq!(shannon_entropy(b"aaaa"), 0.0);
+        assert_eq!(shannon_entropy(b"abcd"), 2.0);
+    }
+}
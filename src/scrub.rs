@@ -0,0 +1,302 @@
+//
+// author: xigang zhao
+//
+
+// Rebuilds a PNG with only the chunks needed to display it, so that it keeps
+// its appearance but carries no hidden payload.
+
+use std::convert::TryFrom;
+use std::str::FromStr;
+use rand::Rng;
+use crate::apng::{self, FrameData};
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::fctl_stego;
+use crate::ihdr::ImageHeader;
+use crate::pixels::Pixels;
+use crate::png::Png;
+use crate::png_error::PngError;
+use crate::policy::Policy;
+use crate::Result;
+
+pub const CRITICAL: [&str; 4] = ["IHDR", "PLTE", "IDAT", "IEND"];
+pub const DEFAULT_KEEP: [&str; 5] = ["tRNS", "gAMA", "cHRM", "sRGB", "pHYs"];
+pub const ANIMATION: [&str; 3] = ["acTL", "fcTL", "fdAT"];
+
+#[derive(Debug, Default)]
+pub struct ScrubOptions {
+    pub keep          : Vec<String>, // ancillary chunk types to keep
+    pub keep_animation: bool,
+    pub pixels        : bool,        // re-encode pixels with random LSBs
+    pub policy        : Option<Policy>, // replaces keep and keep_animation
+}
+
+#[derive(Debug, Default)]
+pub struct ScrubReport {
+    removed         : Vec<(String, u32)>,
+    trailer         : usize,
+    pixels          : bool,
+    lsb_samples     : usize,
+    broken_animation: Option<String>, // why the animation was dropped
+}
+
+impl ScrubReport {
+    pub fn removed(&self) -> &[(String, u32)] {
+        return &self.removed;
+    }
+
+    pub fn trailer(&self) -> usize {
+        return self.trailer;
+    }
+
+    pub fn pixels(&self) -> bool {
+        return self.pixels;
+    }
+
+    pub fn lsb_samples(&self) -> usize {
+        return self.lsb_samples;
+    }
+
+    pub fn broken_animation(&self) -> Option<&str> {
+        return self.broken_animation.as_deref();
+    }
+}
+
+pub fn scrub(png: &Png, options: &ScrubOptions) -> Result<(Png, ScrubReport)> {
+    let mut report = ScrubReport::default();
+    let mut kept: Vec<Chunk> = Vec::new();
+    let mut seen_iend = false;
+    for ck in png.chunks() {
+        let name = ck.chunk_type().to_string();
+        let allowed = match &options.policy {
+            Some(policy) => policy.allows(ck.chunk_type()),
+            None => options.keep.contains(&name) || (options.keep_animation && ANIMATION.contains(&name.as_str())),
+        };
+        let wanted = !seen_iend
+                     && (CRITICAL.contains(&name.as_str()) || allowed)
+                     // a second IHDR is never needed
+                     && (name != "IHDR" || kept.is_empty());
+        seen_iend |= name == "IEND";
+        if wanted {
+            kept.push(Chunk::new(*ck.chunk_type(), ck.data().to_vec()));
+        } else {
+            report.removed.push((name, ck.length()));
+        }
+    }
+    report.trailer = png.trailer().len();
+    let mut clean = Png::from_chunks(kept);
+    // frame control fields can hide bits too. An animation too broken to
+    // clear them is dropped instead, leaving the default image.
+    if apng::is_animated(&clean) && let Err(e) = fctl_stego::clear(&mut clean) {
+        report.broken_animation = Some(e.to_string());
+        drop_animation(&mut clean, &mut report);
+    }
+    if options.pixels {
+        // the frames first, while the IHDR still says whether they are interlaced
+        if apng::is_animated(&clean) {
+            report.lsb_samples += scrub_frames(&mut clean)?;
+        }
+        let mut pixels = Pixels::decode(&clean)?;
+        report.lsb_samples += randomize_lsbs(&mut pixels);
+        pixels.encode(&mut clean)?;
+        report.pixels = true;
+    }
+    return Ok((clean, report));
+}
+
+fn drop_animation(png: &mut Png, report: &mut ScrubReport) {
+    let animation: Vec<usize> = (0..png.chunks().len())
+        .filter(|&i| ANIMATION.contains(&png.chunks()[i].chunk_type().to_string().as_str()))
+        .collect();
+    for &i in &animation {
+        let ck = &png.chunks()[i];
+        report.removed.push((ck.chunk_type().to_string(), ck.length()));
+    }
+    for &i in animation.iter().rev() {
+        png.remove_chunk(i);
+    }
+}
+
+// Sets the lowest bit of every sample at random and returns how many samples
+// there were. Palette indices are left alone, since their low bit picks
+// another color.
+fn randomize_lsbs(pixels: &mut Pixels) -> usize {
+    if pixels.header().color_type() == 3 {
+        return 0;
+    }
+    let mut rng = rand::rng();
+    let offsets = pixels.sample_offsets();
+    for &offset in &offsets {
+        let byte = &mut pixels.data_mut()[offset];
+        *byte = (*byte & !1) | (rng.random::<u8>() & 1);
+    }
+    return offsets.len();
+}
+
+// Re-encodes the frames stored in fdAT chunks like the default image: random
+// LSBs and fresh compression, written non-interlaced in one fdAT chunk per
+// frame. The sequence numbers are redone to match.
+fn scrub_frames(png: &mut Png) -> Result<usize> {
+    let header = ImageHeader::try_from(png.chunk_by_type("IHDR").ok_or(PngError::InvalidImageHeader)?)?;
+    let mut samples = 0;
+    // last frame first, so the chunk indices of the earlier ones stay valid
+    for frame in apng::frames(png)?.iter().rev() {
+        let fdats: Vec<usize> = frame.chunks().iter().copied()
+                                     .filter(|&i| &png.chunks()[i].chunk_type().bytes() == b"fdAT")
+                                     .collect();
+        if fdats.is_empty() {
+            continue;
+        }
+        let mut stream: Vec<u8> = Vec::new();
+        for &i in &fdats {
+            stream.extend_from_slice(FrameData::try_from(&png.chunks()[i])?.data());
+        }
+        let control = frame.control();
+        let mut pixels = Pixels::from_stream(header.with_size(control.width(), control.height())?, &stream)?;
+        samples += randomize_lsbs(&mut pixels);
+        for &i in fdats.iter().rev() {
+            png.remove_chunk(i);
+        }
+        let mut data = vec![0; 4];
+        data.extend_from_slice(&pixels.to_stream());
+        png.insert_chunk(fdats[0], Chunk::new(ChunkType::from_str("fdAT").unwrap(), data));
+    }
+    apng::renumber(png);
+    return Ok(samples);
+}
+
+// Drops the chunks a policy denies and leaves everything else as it is.
+// The critical chunks are always kept, since the image can't be shown without them.
+pub fn remove(png: &Png, policy: &Policy) -> (Png, Vec<(String, u32)>) {
+    let mut removed: Vec<(String, u32)> = Vec::new();
+    let mut kept: Vec<Chunk> = Vec::new();
+    for ck in png.chunks() {
+        let name = ck.chunk_type().to_string();
+        if CRITICAL.contains(&name.as_str()) || policy.allows(ck.chunk_type()) {
+            kept.push(Chunk::new(*ck.chunk_type(), ck.data().to_vec()));
+        } else {
+            removed.push((name, ck.length()));
+        }
+    }
+    let mut clean = Png::from_chunks(kept);
+    clean.set_trailer(png.trailer().to_vec());
+    return (clean, removed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::chunk_type::ChunkType;
+    use crate::pixels::tests::testing_png;
+
+    fn chunk(chunk_type: &str, data: Vec<u8>) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+    }
+
+    fn dirty_png() -> Png {
+        let mut png = testing_png(16, 16, 2, 0);
+        png.insert_chunk(1, chunk("gAMA", 45455u32.to_be_bytes().to_vec()));
+        png.insert_chunk(2, chunk("tEXt", b"Comment\0hello".to_vec()));
+        png.insert_chunk(4, chunk("ruSt", b"hidden".to_vec()));
+        png.append_chunk(chunk("miDl", b"after".to_vec()));
+        png.set_trailer(b"trailer".to_vec());
+        png
+    }
+
+    #[test]
+    fn test_scrub_chunks() {
+        let (clean, report) = scrub(&dirty_png(), &ScrubOptions {
+            keep: DEFAULT_KEEP.iter().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        }).unwrap();
+        let names: Vec<String> = clean.chunks().iter().map(|ck| ck.chunk_type().to_string()).collect();
+        assert_eq!(names, vec!["IHDR", "gAMA", "IDAT", "IEND"]);
+        assert!(clean.trailer().is_empty());
+        assert_eq!(report.removed().len(), 3);
+        assert_eq!(report.trailer(), 7);
+        assert!(!report.pixels());
+    }
+
+    #[test]
+    fn test_scrub_policy() {
+        let policy = Policy::from_str("allow tEXt\ndefault deny").unwrap();
+        let (clean, _) = scrub(&dirty_png(), &ScrubOptions { policy: Some(policy), ..Default::default() }).unwrap();
+        let names: Vec<String> = clean.chunks().iter().map(|ck| ck.chunk_type().to_string()).collect();
+        assert_eq!(names, vec!["IHDR", "tEXt", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_remove() {
+        let policy = Policy::from_str("deny private\ndeny IDAT").unwrap();
+        let (clean, removed) = remove(&dirty_png(), &policy);
+        assert_eq!(removed, vec![("ruSt".to_string(), 6), ("miDl".to_string(), 5)]);
+        assert_eq!(clean.chunks().len(), 5);
+        assert_eq!(clean.trailer(), b"trailer");
+    }
+
+    // 8x8 RGBA animation whose second frame is a 4x4 fdAT frame with every
+    // low bit set.
+    fn animated_png() -> Png {
+        let fctl = |seq: u32, size: u32| {
+            let mut data = seq.to_be_bytes().to_vec();
+            for value in [size, size, 0, 0] {
+                data.extend_from_slice(&value.to_be_bytes());
+            }
+            data.extend_from_slice(&[0, 1, 0, 10, 0, 0]);
+            chunk("fcTL", data)
+        };
+        let raw: Vec<u8> = (0..4).flat_map(|_| [0].into_iter().chain([0xff; 16])).collect();
+        let mut fdat = 2u32.to_be_bytes().to_vec();
+        fdat.extend_from_slice(&crate::zlib::deflate(&raw));
+        let mut png = testing_png(8, 8, 6, 0);
+        png.insert_chunk(1, chunk("acTL", [2u32.to_be_bytes(), 0u32.to_be_bytes()].concat()));
+        png.insert_chunk(2, fctl(0, 8));
+        png.insert_chunk(4, fctl(1, 4));
+        png.insert_chunk(5, chunk("fdAT", fdat));
+        png
+    }
+
+    #[test]
+    fn test_scrub_animation_pixels() {
+        let png = animated_png();
+        assert!(apng::validate(&png).is_ok());
+        let options = ScrubOptions { pixels: true, keep_animation: true, ..Default::default() };
+        let (clean, report) = scrub(&png, &options).unwrap();
+        assert_eq!(report.lsb_samples(), 8 * 8 * 4 + 4 * 4 * 4);
+        assert!(apng::validate(&clean).is_ok());
+        let frame = FrameData::try_from(clean.chunk_by_type("fdAT").unwrap()).unwrap();
+        let header = ImageHeader::try_from(clean.chunk_by_type("IHDR").unwrap()).unwrap();
+        let pixels = Pixels::from_stream(header.with_size(4, 4).unwrap(), frame.data()).unwrap();
+        assert!(pixels.data().iter().all(|&b| b >> 1 == 0x7f));
+        assert!(pixels.data().iter().any(|&b| b & 1 == 0));
+    }
+
+    #[test]
+    fn test_scrub_broken_animation() {
+        let mut png = animated_png();
+        // the fdAT claims a sequence number out of order
+        let mut fdat = png.chunks()[5].data().to_vec();
+        fdat[..4].copy_from_slice(&7u32.to_be_bytes());
+        png.set_chunk(5, chunk("fdAT", fdat));
+        assert!(apng::validate(&png).is_err());
+        let options = ScrubOptions { pixels: true, keep_animation: true, ..Default::default() };
+        let (clean, report) = scrub(&png, &options).unwrap();
+        assert!(report.broken_animation().is_some());
+        assert!(!apng::is_animated(&clean));
+        let names: Vec<String> = clean.chunks().iter().map(|ck| ck.chunk_type().to_string()).collect();
+        assert_eq!(names, vec!["IHDR", "IDAT", "IEND"]);
+        assert_eq!(report.removed().len(), 4);
+        assert_eq!(report.lsb_samples(), 8 * 8 * 4);
+    }
+
+    #[test]
+    fn test_scrub_pixels() {
+        let png = dirty_png();
+        let (clean, report) = scrub(&png, &ScrubOptions { pixels: true, ..Default::default() }).unwrap();
+        assert_eq!(report.lsb_samples(), 16 * 16 * 3);
+        let before = Pixels::decode(&png).unwrap();
+        let after = Pixels::decode(&clean).unwrap();
+        assert!(before.data().iter().zip(after.data()).all(|(a, b)| a >> 1 == b >> 1));
+    }
+}
@@ -0,0 +1,101 @@
+//
+// author: xigang zhao
+//
+
+// Reading passwords without putting them on the command line, where they end
+// up in shell history and in `ps`. A password comes from one of
+//
+//     prompt        the terminal, without echo
+//     env:VAR       an environment variable
+//     fd:N          the first line read from an open file descriptor
+//     file:PATH     the first line of a file
+//
+// The value is kept in a buffer that is wiped when it is dropped, and its
+// Debug form doesn't show it.
+
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use zeroize::Zeroizing;
+use crate::secret_error::SecretError;
+use crate::Result;
+
+pub const PROMPT: &str = "prompt";
+
+pub struct Secret(Zeroizing<String>);
+
+impl Secret {
+    pub fn as_str(&self) -> &str {
+        return &self.0;
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "Secret(..)");
+    }
+}
+
+// Reads a password from `source`, asking with `prompt` on the terminal. With
+// `confirm` a typed password has to be typed twice.
+pub fn read(source: &str, prompt: &str, confirm: bool) -> Result<Secret> {
+    let secret = match source.split_once(':') {
+        _ if source == PROMPT => {
+            let secret = ask(&format!("{}: ", prompt))?;
+            if confirm && *secret != *ask(&format!("{} again: ", prompt))? {
+                return Err(Box::from(SecretError::Mismatch));
+            }
+            secret
+        }
+        Some(("env", var)) => {
+            Zeroizing::new(std::env::var(var).map_err(|_| SecretError::MissingVariable(var.to_string()))?)
+        }
+        Some(("fd", fd)) => {
+            let fd = fd.parse::<u32>().map_err(|_| SecretError::InvalidDescriptor)?;
+            let mut content = Zeroizing::new(String::new());
+            fs::File::open(format!("/dev/fd/{}", fd))?.read_to_string(&mut content)?;
+            first_line(&content)
+        }
+        Some(("file", path)) => first_line(&Zeroizing::new(fs::read_to_string(path)?)),
+        _ => return Err(Box::from(SecretError::UnknownSource)),
+    };
+    if secret.is_empty() {
+        return Err(Box::from(SecretError::EmptySecret));
+    }
+    return Ok(Secret(secret));
+}
+
+fn ask(prompt: &str) -> Result<Zeroizing<String>> {
+    let secret = rpassword::prompt_password(prompt).map_err(|_| SecretError::NoTerminal)?;
+    return Ok(Zeroizing::new(secret));
+}
+
+fn first_line(content: &str) -> Zeroizing<String> {
+    let line = content.lines().next().unwrap_or("");
+    return Zeroizing::new(line.to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file() {
+        let path = std::env::temp_dir().join(format!("deepng-secret-{}", std::process::id()));
+        fs::write(&path, "correct horse\r\nsecond line\n").unwrap();
+        let secret = read(&format!("file:{}", path.display()), "Password", false).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(secret.as_str(), "correct horse");
+        assert_eq!(format!("{:?}", secret), "Secret(..)");
+    }
+
+    #[test]
+    fn test_errors_hide_the_value() {
+        // a password given by habit instead of a source
+        let err = read("hunter2", "Password", false).unwrap_err();
+        assert!(matches!(err.downcast_ref::<SecretError>(), Some(SecretError::UnknownSource)));
+        assert!(!format!("{} {:?}", err, err).contains("hunter2"));
+        assert!(read("env:DEEPNG_NO_SUCH_VARIABLE", "Password", false).is_err());
+        assert!(read("fd:stdin", "Password", false).is_err());
+    }
+}
@@ -0,0 +1,31 @@
+//
+// author: xigang zhao
+//
+
+use std::fmt;
+
+// None of these carry the value that was given, which may be the secret.
+#[derive(Debug)]
+pub enum SecretError {
+    UnknownSource,
+    MissingVariable(String),
+    InvalidDescriptor,
+    NoTerminal,
+    EmptySecret,
+    Mismatch,
+}
+
+impl fmt::Display for SecretError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SecretError::UnknownSource        => write!(f, "Unknown password source, expected prompt, env:VAR, fd:N or file:PATH"),
+            SecretError::MissingVariable(var) => write!(f, "The environment variable {} is not set", var),
+            SecretError::InvalidDescriptor    => write!(f, "fd: needs the number of an open file descriptor"),
+            SecretError::NoTerminal           => write!(f, "No terminal to ask for the password on, use env:VAR, fd:N or file:PATH"),
+            SecretError::EmptySecret          => write!(f, "The password is empty"),
+            SecretError::Mismatch             => write!(f, "The passwords don't match"),
+        }
+    }
+}
+
+impl std::error::Error for SecretError {}
@@ -0,0 +1,37 @@
+//
+// author: xigang zhao
+//
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum ShamirError {
+    InvalidShareCount(usize),
+    InvalidThreshold(usize, usize),
+    CarrierCountMismatch(usize, usize),
+    InvalidShare,
+    MixedSets,
+    NotEnoughShares(usize, usize),
+    DigestMismatch,
+    Share,
+}
+
+impl fmt::Display for ShamirError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShamirError::InvalidShareCount(count)         => write!(f, "A secret can be split into 2 to 255 shares, not {}", count),
+            ShamirError::InvalidThreshold(threshold, count) =>
+                write!(f, "The threshold must be between 2 and the number of shares ({}), not {}", count, threshold),
+            ShamirError::CarrierCountMismatch(count, carriers) =>
+                write!(f, "{} shares need {} carrier files, not {}", count, count, carriers),
+            ShamirError::InvalidShare                     => write!(f, "Truncated or malformed share"),
+            ShamirError::MixedSets                        => write!(f, "The shares belong to more than one secret"),
+            ShamirError::NotEnoughShares(found, threshold) =>
+                write!(f, "Found {} distinct shares, {} are needed", found, threshold),
+            ShamirError::DigestMismatch                   => write!(f, "The recovered secret does not match its digest, a share is damaged"),
+            ShamirError::Share                            => write!(f, "This is one share of a split secret, decode it with --spread"),
+        }
+    }
+}
+
+impl std::error::Error for ShamirError {}
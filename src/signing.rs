@@ -0,0 +1,263 @@
+//
+// author: xigang zhao
+//
+
+// Ed25519 signatures over a canonical digest of a PNG. The digest covers every
+// critical chunk, the ancillary chunks that change how the image or its
+// animation looks, and the ancillary chunk types the signer lists, in file
+// order, so re-saving a file that leaves those chunks alone keeps the
+// signature valid. The signature is stored in a private dsIG chunk:
+//
+//     version (1) | public key (32) | n (1) | n included chunk types | signature (64)
+
+use std::convert::TryFrom;
+use std::str::FromStr;
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey, PUBLIC_KEY_LENGTH, SIGNATURE_LENGTH};
+use sha2::{Digest, Sha256};
+use crate::apng;
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::signing_error::SigningError;
+use crate::Result;
+
+pub const SIGNATURE_CHUNK: &str = "dsIG";
+const VERSION: u8 = 1;
+const DOMAIN: &[u8] = b"deepng signature\0";
+// ancillary chunks that are always signed, since changing them changes what is shown
+pub const ALWAYS_COVERED: [&str; 8] = ["acTL", "fcTL", "fdAT", "tRNS", "gAMA", "cHRM", "sRGB", "iCCP"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureChunk {
+    public_key: VerifyingKey,
+    included  : Vec<ChunkType>,
+    signature : Signature,
+}
+
+#[derive(Debug)]
+pub struct Verification {
+    public_key: VerifyingKey,
+    trusted   : bool,
+    digest    : [u8; 32],
+    covered   : Vec<usize>,
+    uncovered : Vec<usize>,
+}
+
+impl TryFrom<&Chunk> for SignatureChunk {
+    type Error = SigningError;
+    fn try_from(ck: &Chunk) -> std::result::Result<Self, Self::Error> {
+        let data = ck.data();
+        if ck.chunk_type().to_string() != SIGNATURE_CHUNK || data.len() < 2 + PUBLIC_KEY_LENGTH || data[0] != VERSION {
+            return Err(SigningError::InvalidSignatureChunk);
+        }
+        let public_key = VerifyingKey::from_bytes(data[1..1 + PUBLIC_KEY_LENGTH].try_into().unwrap())
+                                      .map_err(|_| SigningError::InvalidSignatureChunk)?;
+        let n = data[1 + PUBLIC_KEY_LENGTH] as usize;
+        let types_start = 2 + PUBLIC_KEY_LENGTH;
+        if data.len() != types_start + 4 * n + SIGNATURE_LENGTH {
+            return Err(SigningError::InvalidSignatureChunk);
+        }
+        let included = data[types_start..types_start + 4 * n]
+                           .chunks_exact(4)
+                           .map(|t| ChunkType::try_from(<[u8; 4]>::try_from(t).unwrap()))
+                           .collect::<std::result::Result<Vec<ChunkType>, _>>()
+                           .map_err(|_| SigningError::InvalidSignatureChunk)?;
+        let signature = Signature::from_slice(&data[types_start + 4 * n..])
+                                  .map_err(|_| SigningError::InvalidSignatureChunk)?;
+        return Ok(SignatureChunk { public_key, included, signature });
+    }
+}
+
+impl SignatureChunk {
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data = signed_fields(&self.public_key, &self.included);
+        data.extend_from_slice(&self.signature.to_bytes());
+        return Chunk::new(ChunkType::from_str(SIGNATURE_CHUNK).unwrap(), data);
+    }
+}
+
+impl Verification {
+    pub fn public_key(&self) -> &VerifyingKey {
+        return &self.public_key;
+    }
+
+    // Whether the signer's key was checked against a trusted key.
+    pub fn trusted(&self) -> bool {
+        return self.trusted;
+    }
+
+    pub fn digest(&self) -> &[u8; 32] {
+        return &self.digest;
+    }
+
+    // Indices of the chunks the signature covers.
+    pub fn covered(&self) -> &[usize] {
+        return &self.covered;
+    }
+
+    // Indices of the chunks anyone could change without breaking the signature.
+    pub fn uncovered(&self) -> &[usize] {
+        return &self.uncovered;
+    }
+}
+
+pub fn covers(chunk_type: &ChunkType, included: &[ChunkType]) -> bool {
+    let name = chunk_type.to_string();
+    return name != SIGNATURE_CHUNK
+           && (chunk_type.is_critical() || ALWAYS_COVERED.contains(&name.as_str()) || included.contains(chunk_type));
+}
+
+pub fn digest(png: &Png, included: &[ChunkType]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for ck in png.chunks().iter().filter(|ck| covers(ck.chunk_type(), included)) {
+        hasher.update(ck.chunk_type().bytes());
+        hasher.update(ck.length().to_be_bytes());
+        hasher.update(ck.data());
+    }
+    return hasher.finalize().into();
+}
+
+// Replaces any earlier signature.
+pub fn sign(png: &mut Png, key: &SigningKey, included: &[ChunkType]) -> Result<()> {
+    let mut unique: Vec<ChunkType> = Vec::new();
+    for t in included {
+        if t.is_critical() || t.to_string() == SIGNATURE_CHUNK {
+            return Err(Box::from(SigningError::InvalidInclude(t.to_string())));
+        }
+        if !unique.contains(t) && !ALWAYS_COVERED.contains(&t.to_string().as_str()) {
+            unique.push(*t);
+        }
+    }
+    let included = unique;
+    if included.len() > u8::MAX as usize {
+        return Err(Box::from(SigningError::InvalidInclude(included[u8::MAX as usize].to_string())));
+    }
+    while png.remove_first_chunk(SIGNATURE_CHUNK).is_ok() {}
+    let public_key = key.verifying_key();
+    let message = message(&public_key, &included, &digest(png, &included));
+    let signature = key.sign(&message);
+    let chunk = SignatureChunk { public_key, included, signature }.to_chunk();
+    png.insert_chunk(apng::hidden_chunk_position(png), chunk);
+    return Ok(());
+}
+
+// Checks the signature, and the signer against `trusted` when it is given.
+pub fn verify(png: &Png, trusted: Option<&VerifyingKey>) -> Result<Verification> {
+    let ck = png.chunk_by_type(SIGNATURE_CHUNK).ok_or(SigningError::MissingSignature)?;
+    let sig = SignatureChunk::try_from(ck)?;
+    if let Some(trusted) = trusted && trusted != &sig.public_key {
+        return Err(Box::from(SigningError::UntrustedKey));
+    }
+    let digest = digest(png, &sig.included);
+    sig.public_key.verify_strict(&message(&sig.public_key, &sig.included, &digest), &sig.signature)
+                  .map_err(|_| SigningError::BadSignature)?;
+    let (covered, uncovered): (Vec<usize>, Vec<usize>) =
+        (0..png.chunks().len()).filter(|&i| png.chunks()[i].chunk_type().to_string() != SIGNATURE_CHUNK)
+                               .partition(|&i| covers(png.chunks()[i].chunk_type(), &sig.included));
+    return Ok(Verification {
+        public_key: sig.public_key,
+        trusted   : trusted.is_some(),
+        digest,
+        covered,
+        uncovered,
+    });
+}
+
+fn signed_fields(public_key: &VerifyingKey, included: &[ChunkType]) -> Vec<u8> {
+    let mut data = vec![VERSION];
+    data.extend_from_slice(public_key.as_bytes());
+    data.push(included.len() as u8);
+    for t in included {
+        data.extend_from_slice(&t.bytes());
+    }
+    return data;
+}
+
+// The signature also covers the key and the list of included types, so
+// neither can be swapped out.
+fn message(public_key: &VerifyingKey, included: &[ChunkType], digest: &[u8; 32]) -> Vec<u8> {
+    let mut message = DOMAIN.to_vec();
+    message.extend_from_slice(&signed_fields(public_key, included));
+    message.extend_from_slice(digest);
+    return message;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys;
+    use crate::pixels::tests::testing_png;
+
+    fn chunk(chunk_type: &str, data: Vec<u8>) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+    }
+
+    fn testing_signed() -> (Png, SigningKey) {
+        let mut png = testing_png(8, 8, 2, 0);
+        png.insert_chunk(1, chunk("tEXt", b"Author\0us".to_vec()));
+        png.insert_chunk(1, chunk("tIME", vec![7, 234, 10, 19, 12, 0, 0]));
+        let key = keys::generate();
+        sign(&mut png, &key, &[ChunkType::from_str("tEXt").unwrap()]).unwrap();
+        (png, key)
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let (png, key) = testing_signed();
+        let png = Png::try_from(png.as_bytes().as_ref()).unwrap();
+        let verification = verify(&png, Some(&key.verifying_key())).unwrap();
+        assert!(verification.trusted());
+        // IHDR, tEXt, IDAT, IEND but not tIME
+        assert_eq!(verification.covered(), &[0, 2, 3, 5]);
+        assert_eq!(verification.uncovered(), &[1]);
+    }
+
+    #[test]
+    fn test_tampered() {
+        let (mut png, _) = testing_signed();
+        png.set_chunk(2, chunk("tEXt", b"Author\0them".to_vec()));
+        assert!(verify(&png, None).is_err());
+    }
+
+    #[test]
+    fn test_uncovered_change() {
+        let (mut png, _) = testing_signed();
+        png.set_chunk(1, chunk("tIME", vec![7, 234, 1, 1, 0, 0, 0]));
+        png.set_trailer(b"extra".to_vec());
+        assert!(verify(&png, None).is_ok());
+    }
+
+    #[test]
+    fn test_rendering_chunks_covered() {
+        let (mut png, key) = testing_signed();
+        png.insert_chunk(1, chunk("gAMA", 45455u32.to_be_bytes().to_vec()));
+        // covered without --include
+        sign(&mut png, &key, &[]).unwrap();
+        assert!(verify(&png, None).unwrap().covered().contains(&1));
+        png.set_chunk(1, chunk("gAMA", 100000u32.to_be_bytes().to_vec()));
+        assert!(verify(&png, None).is_err());
+    }
+
+    #[test]
+    fn test_untrusted_key() {
+        let (png, _) = testing_signed();
+        let other = keys::generate();
+        assert!(matches!(verify(&png, Some(&other.verifying_key())).unwrap_err().downcast_ref::<SigningError>(),
+                         Some(SigningError::UntrustedKey)));
+    }
+
+    #[test]
+    fn test_resign() {
+        let (mut png, key) = testing_signed();
+        sign(&mut png, &key, &[]).unwrap();
+        let signatures = png.chunks().iter().filter(|ck| ck.chunk_type().to_string() == SIGNATURE_CHUNK).count();
+        assert_eq!(signatures, 1);
+        assert_eq!(verify(&png, None).unwrap().uncovered().len(), 2);
+    }
+
+    #[test]
+    fn test_invalid_include() {
+        let mut png = testing_png(8, 8, 2, 0);
+        assert!(sign(&mut png, &keys::generate(), &[ChunkType::from_str("IDAT").unwrap()]).is_err());
+    }
+}
@@ -0,0 +1,33 @@
+//
+// author: xigang zhao
+//
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum SigningError {
+    InvalidKey,
+    InvalidSignatureChunk,
+    MissingSignature,
+    BadSignature,
+    UntrustedKey,
+    InvalidInclude(String),
+    PrivateKeyPath(String),
+}
+
+impl fmt::Display for SigningError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SigningError::InvalidKey            => write!(f, "Not an Ed25519 key in PEM, hex or raw form"),
+            SigningError::InvalidSignatureChunk => write!(f, "Malformed signature chunk"),
+            SigningError::MissingSignature      => write!(f, "The file is not signed"),
+            SigningError::BadSignature          => write!(f, "The signature does not match the signed chunks"),
+            SigningError::UntrustedKey          => write!(f, "The file was signed with a different key"),
+            SigningError::InvalidInclude(t)     =>
+                write!(f, "{} cannot be included: critical chunks are always signed and the signature chunk never is", t),
+            SigningError::PrivateKeyPath(p)     => write!(f, "{} is where a public key goes, not a private key", p),
+        }
+    }
+}
+
+impl std::error::Error for SigningError {}
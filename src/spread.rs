@@ -0,0 +1,191 @@
+//
+// author: xigang zhao
+//
+
+// Spreads one payload over several carrier images. The payload container is
+// cut into equal parts, and each carrier gets a container of its own, flagged
+// FRAGMENTED, that holds one part:
+//
+//     set id (8) | index (1) | count (1) | manifest | part
+//
+// The manifest is the same in every fragment: the payload size (4), its
+// SHA-256 (32) and the first 8 bytes of the SHA-256 of each part. With it any
+// one fragment tells which others are needed and whether they are intact.
+
+use sha2::{Digest, Sha256};
+use crate::container::{self, PackOptions};
+use crate::spread_error::SpreadError;
+use crate::Result;
+
+pub const MIN_CARRIERS: usize = 2;
+pub const MAX_CARRIERS: usize = u8::MAX as usize;
+
+const SET_ID_SIZE: usize = 8;
+const PART_DIGEST_SIZE: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Fragment {
+    set_id  : [u8; SET_ID_SIZE],
+    index   : usize,
+    count   : usize,
+    manifest: Vec<u8>,
+    part    : Vec<u8>,
+}
+
+impl Fragment {
+    fn parse(data: &[u8]) -> Result<Fragment> {
+        let invalid = || SpreadError::InvalidFragment;
+        let set_id: [u8; SET_ID_SIZE] = data.get(..SET_ID_SIZE).ok_or_else(invalid)?.try_into().unwrap();
+        let index = *data.get(SET_ID_SIZE).ok_or_else(invalid)? as usize;
+        let count = *data.get(SET_ID_SIZE + 1).ok_or_else(invalid)? as usize;
+        let start = SET_ID_SIZE + 2;
+        let manifest = data.get(start..start + manifest_size(count)).ok_or_else(invalid)?;
+        if count < MIN_CARRIERS || index >= count {
+            return Err(Box::from(SpreadError::InvalidFragment));
+        }
+        return Ok(Fragment {
+            set_id  : set_id,
+            index   : index,
+            count   : count,
+            manifest: manifest.to_vec(),
+            part    : data[start + manifest.len()..].to_vec(),
+        });
+    }
+
+    fn size(&self) -> usize {
+        return u32::from_be_bytes(self.manifest[..4].try_into().unwrap()) as usize;
+    }
+
+    fn digest(&self) -> &[u8] {
+        return &self.manifest[4..36];
+    }
+
+    fn part_digest(&self, index: usize) -> &[u8] {
+        let start = 36 + index * PART_DIGEST_SIZE;
+        return &self.manifest[start..start + PART_DIGEST_SIZE];
+    }
+}
+
+fn manifest_size(count: usize) -> usize {
+    return 4 + 32 + count * PART_DIGEST_SIZE;
+}
+
+// Cuts a packed payload into `count` fragment containers, one per carrier.
+pub fn split(packed: &[u8], count: usize, name: Option<&str>) -> Result<Vec<Vec<u8>>> {
+    if !(MIN_CARRIERS..=MAX_CARRIERS).contains(&count) {
+        return Err(Box::from(SpreadError::InvalidCount(count)));
+    }
+    let part_size = packed.len().div_ceil(count);
+    let parts: Vec<&[u8]> = (0..count).map(|i| {
+        let start = (i * part_size).min(packed.len());
+        &packed[start..(start + part_size).min(packed.len())]
+    }).collect();
+    let mut manifest = (packed.len() as u32).to_be_bytes().to_vec();
+    manifest.extend_from_slice(&Sha256::digest(packed));
+    for part in &parts {
+        manifest.extend_from_slice(&Sha256::digest(part)[..PART_DIGEST_SIZE]);
+    }
+    let set_id: [u8; SET_ID_SIZE] = rand::random();
+    let options = PackOptions { name: name.map(str::to_string), fragment: true, ..Default::default() };
+    let mut fragments: Vec<Vec<u8>> = Vec::new();
+    for (i, part) in parts.iter().enumerate() {
+        let mut data = set_id.to_vec();
+        data.push(i as u8);
+        data.push(count as u8);
+        data.extend_from_slice(&manifest);
+        data.extend_from_slice(part);
+        fragments.push(container::pack(&data, &options)?);
+    }
+    return Ok(fragments);
+}
+
+// Rebuilds the packed payload from the data of fragment containers, given
+// in any order. The same fragment may be given more than once.
+pub fn join(fragments: &[Vec<u8>]) -> Result<Vec<u8>> {
+    let fragments: Vec<Fragment> = fragments.iter().map(|data| Fragment::parse(data)).collect::<Result<_>>()?;
+    let first = fragments.first().ok_or(SpreadError::NoFragments)?;
+    if fragments.iter().any(|f| f.set_id != first.set_id || f.count != first.count || f.manifest != first.manifest) {
+        return Err(Box::from(SpreadError::MixedSets));
+    }
+    let mut parts: Vec<Option<&Fragment>> = vec![None; first.count];
+    for fragment in &fragments {
+        parts[fragment.index] = Some(fragment);
+    }
+    let missing: Vec<usize> = (0..first.count).filter(|&i| parts[i].is_none()).map(|i| i + 1).collect();
+    if !missing.is_empty() {
+        return Err(Box::from(SpreadError::MissingFragments(missing, first.count)));
+    }
+    let mut packed: Vec<u8> = Vec::new();
+    for (i, fragment) in parts.iter().flatten().enumerate() {
+        if Sha256::digest(&fragment.part)[..PART_DIGEST_SIZE] != *first.part_digest(i) {
+            return Err(Box::from(SpreadError::CorruptFragment(i + 1, first.count)));
+        }
+        packed.extend_from_slice(&fragment.part);
+    }
+    if packed.len() != first.size() || Sha256::digest(&packed).as_slice() != first.digest() {
+        return Err(Box::from(SpreadError::DigestMismatch));
+    }
+    return Ok(packed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fragments(packed: &[u8], count: usize) -> Vec<Vec<u8>> {
+        split(packed, count, Some("notes.txt")).unwrap()
+                                               .iter()
+                                               .map(|f| container::unpack(f).unwrap().into_data())
+                                               .collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let packed = b"a payload that is too large for one image".to_vec();
+        let containers = split(&packed, 3, Some("notes.txt")).unwrap();
+        let payload = container::unpack(&containers[0]).unwrap();
+        assert_eq!(payload.flags(), container::FRAGMENTED);
+        assert_eq!(payload.name(), Some("notes.txt"));
+        let mut data = fragments(&packed, 3);
+        data.reverse();
+        data.push(data[0].clone());
+        assert_eq!(join(&data).unwrap(), packed);
+    }
+
+    #[test]
+    fn test_small_payload() {
+        assert_eq!(join(&fragments(b"ab", 4)).unwrap(), b"ab");
+    }
+
+    #[test]
+    fn test_missing() {
+        let mut data = fragments(&[7; 100], 5);
+        data.remove(3);
+        data.remove(1);
+        let e = join(&data).unwrap_err();
+        assert!(matches!(e.downcast_ref::<SpreadError>(), Some(SpreadError::MissingFragments(m, 5)) if *m == vec![2, 4]));
+        assert_eq!(e.to_string(), "Missing fragment 2, 4 of 5");
+    }
+
+    #[test]
+    fn test_mixed_sets() {
+        let mut data = fragments(&[7; 100], 2);
+        data[1] = fragments(&[7; 100], 2).remove(1);
+        assert!(matches!(join(&data).unwrap_err().downcast_ref::<SpreadError>(), Some(SpreadError::MixedSets)));
+    }
+
+    #[test]
+    fn test_corrupt() {
+        let mut data = fragments(&[7; 100], 2);
+        let last = data[1].len() - 1;
+        data[1][last] ^= 1;
+        assert!(matches!(join(&data).unwrap_err().downcast_ref::<SpreadError>(), Some(SpreadError::CorruptFragment(2, 2))));
+    }
+
+    #[test]
+    fn test_count() {
+        assert!(split(b"data", 1, None).is_err());
+        assert!(split(b"data", 256, None).is_err());
+        assert!(join(&[]).is_err());
+    }
+}
@@ -0,0 +1,37 @@
+//
+// author: xigang zhao
+//
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum SpreadError {
+    InvalidCount(usize),
+    InvalidFragment,
+    NoFragments,
+    MixedSets,
+    MissingFragments(Vec<usize>, usize),
+    CorruptFragment(usize, usize),
+    DigestMismatch,
+    Fragment,
+}
+
+impl fmt::Display for SpreadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpreadError::InvalidCount(count)          => write!(f, "A payload can be spread over 2 to 255 carriers, not {}", count),
+            SpreadError::InvalidFragment              => write!(f, "Truncated or malformed fragment"),
+            SpreadError::NoFragments                  => write!(f, "None of the files carries a fragment"),
+            SpreadError::MixedSets                    => write!(f, "The fragments belong to more than one spread payload"),
+            SpreadError::MissingFragments(missing, count) => {
+                let missing: Vec<String> = missing.iter().map(|n| n.to_string()).collect();
+                write!(f, "Missing fragment {} of {}", missing.join(", "), count)
+            }
+            SpreadError::CorruptFragment(n, count)    => write!(f, "Fragment {} of {} does not match the manifest", n, count),
+            SpreadError::DigestMismatch               => write!(f, "The rebuilt payload does not match the manifest"),
+            SpreadError::Fragment                     => write!(f, "This is one fragment of a spread payload, decode it with --spread"),
+        }
+    }
+}
+
+impl std::error::Error for SpreadError {}
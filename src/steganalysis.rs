@@ -0,0 +1,404 @@
+//
+// author: xigang zhao
+//
+
+// Statistical detectors for LSB embedding in the decoded pixels: the
+// chi-square attack (Westfeld & Pfitzmann), RS analysis (Fridrich et al.)
+// and sample pair analysis (Dumitrescu et al.). Each estimates the fraction
+// of samples whose LSB carries a message.
+
+use std::fmt;
+use crate::pixels::Pixels;
+use crate::png_error::PngError;
+use crate::Result;
+
+const HEATMAP: &[u8] = b" .:-=+*#%@";
+const CHI_SQUARE_STEPS: usize = 20;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelAnalysis {
+    channel        : usize,
+    chi_square_p   : f64,
+    chi_square_rate: f64,
+    rs_rate        : f64,
+    spa_rate       : f64,
+}
+
+#[derive(Debug)]
+pub struct Analysis {
+    width   : u32,
+    height  : u32,
+    channels: Vec<ChannelAnalysis>,
+    rows    : usize,
+    cols    : usize,
+    regions : Vec<f64>, // SPA estimate per region, averaged over channels
+}
+
+// One channel of the image as a width x height plane of samples.
+struct Plane {
+    width : usize,
+    values: Vec<i32>,
+}
+
+impl fmt::Display for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "image: {}x{}", self.width, self.height)?;
+        writeln!(f, "channel  chi2 p   chi2 rate  RS rate  SPA rate")?;
+        for c in &self.channels {
+            writeln!(f, "{:>7}  {:>6.3}  {:>9.3}  {:>7.3}  {:>8.3}",
+                     c.channel, c.chi_square_p, c.chi_square_rate, c.rs_rate, c.spa_rate)?;
+        }
+        write!(f, "estimated embedding rate: {:.3}", self.rate())?;
+        if self.regions.is_empty() {
+            return Ok(());
+        }
+        write!(f, "\nregions ({}x{}, SPA, ' ' = 0 .. '@' = 1):", self.rows, self.cols)?;
+        for row in self.regions.chunks(self.cols) {
+            let line: String = row.iter()
+                                  .map(|r| HEATMAP[(r * (HEATMAP.len() - 1) as f64).round() as usize] as char)
+                                  .collect();
+            write!(f, "\n|{}|", line)?;
+        }
+        Ok(())
+    }
+}
+
+impl Analysis {
+    // The RS and SPA estimates agree well on natural images, so the overall
+    // rate is their mean over all channels.
+    pub fn rate(&self) -> f64 {
+        if self.channels.is_empty() {
+            return 0.0;
+        }
+        let sum: f64 = self.channels.iter().map(|c| (c.rs_rate + c.spa_rate) / 2.0).sum();
+        return sum / self.channels.len() as f64;
+    }
+
+    pub fn to_json(&self) -> String {
+        let channels: Vec<String> = self.channels.iter().map(|c| format!(
+            "{{\"channel\":{},\"chi_square_p\":{:.4},\"chi_square_rate\":{:.4},\"rs_rate\":{:.4},\"spa_rate\":{:.4}}}",
+            c.channel, c.chi_square_p, c.chi_square_rate, c.rs_rate, c.spa_rate)).collect();
+        let rows: Vec<String> = self.regions.chunks(self.cols.max(1)).map(|row| {
+            let values: Vec<String> = row.iter().map(|r| format!("{:.4}", r)).collect();
+            format!("[{}]", values.join(","))
+        }).collect();
+        return format!("{{\"width\":{},\"height\":{},\"rate\":{:.4},\"channels\":[{}],\"regions\":{{\"rows\":{},\"cols\":{},\"spa_rate\":[{}]}}}}",
+                       self.width, self.height, self.rate(), channels.join(","), self.rows, self.cols, rows.join(","));
+    }
+}
+
+impl Plane {
+    fn region(&self, x0: usize, y0: usize, width: usize, height: usize) -> Plane {
+        let mut values: Vec<i32> = Vec::with_capacity(width * height);
+        for y in y0..y0 + height {
+            values.extend_from_slice(&self.values[y * self.width + x0..y * self.width + x0 + width]);
+        }
+        return Plane { width, values };
+    }
+
+    fn rows(&self) -> std::slice::Chunks<'_, i32> {
+        return self.values.chunks(self.width);
+    }
+}
+
+pub fn analyze(pixels: &Pixels, grid: usize) -> Result<Analysis> {
+    let header = pixels.header();
+    if header.color_type() == 3 || header.bit_depth() < 8 {
+        return Err(Box::from(PngError::UnsupportedImage));
+    }
+    let width = header.width() as usize;
+    let height = header.height() as usize;
+    let count = header.channels();
+    let samples = pixels.samples();
+    let planes: Vec<Plane> = (0..count).map(|c| Plane {
+        width,
+        values: samples.iter().skip(c).step_by(count).map(|&s| s as i32).collect(),
+    }).collect();
+    let channels: Vec<ChannelAnalysis> = planes.iter().enumerate().map(|(c, plane)| {
+        let (p, rate) = chi_square(plane);
+        ChannelAnalysis { channel: c, chi_square_p: p, chi_square_rate: rate, rs_rate: rs(plane), spa_rate: spa(plane) }
+    }).collect();
+    let rows = grid.min(height);
+    let cols = grid.min(width);
+    let mut regions: Vec<f64> = Vec::with_capacity(rows * cols);
+    for r in 0..rows {
+        for c in 0..cols {
+            let (y0, y1) = (r * height / rows, (r + 1) * height / rows);
+            let (x0, x1) = (c * width / cols, (c + 1) * width / cols);
+            let sum: f64 = planes.iter().map(|p| spa(&p.region(x0, y0, x1 - x0, y1 - y0))).sum();
+            regions.push(sum / planes.len() as f64);
+        }
+    }
+    return Ok(Analysis { width: header.width(), height: header.height(), channels, rows, cols, regions });
+}
+
+// Probability that the pair-of-values histogram is as balanced as LSB
+// replacement makes it, and the largest leading fraction of the image for
+// which that probability stays above one half (sequential embedding).
+fn chi_square(plane: &Plane) -> (f64, f64) {
+    let p = chi_square_p(&plane.values);
+    let mut rate = 0.0;
+    for step in 1..=CHI_SQUARE_STEPS {
+        let end = plane.values.len() * step / CHI_SQUARE_STEPS;
+        if chi_square_p(&plane.values[..end]) > 0.5 {
+            rate = step as f64 / CHI_SQUARE_STEPS as f64;
+        }
+    }
+    return (p, rate);
+}
+
+fn chi_square_p(values: &[i32]) -> f64 {
+    let mut histogram = [0u64; 256];
+    for v in values {
+        histogram[(v & 0xff) as usize] += 1;
+    }
+    let mut chi = 0.0;
+    let mut pairs = 0;
+    for k in 0..128 {
+        let (even, odd) = (histogram[2 * k] as f64, histogram[2 * k + 1] as f64);
+        let expected = (even + odd) / 2.0;
+        if expected > 4.0 {
+            chi += (even - expected).powi(2) / expected;
+            pairs += 1;
+        }
+    }
+    if pairs < 2 {
+        return 0.0;
+    }
+    return 1.0 - regularized_gamma((pairs - 1) as f64 / 2.0, chi / 2.0);
+}
+
+fn flip(x: i32, mask: i32) -> i32 {
+    match mask {
+        1 => x ^ 1,
+        -1 => ((x + 1) ^ 1) - 1,
+        _ => x,
+    }
+}
+
+fn smoothness(group: &[i32]) -> i32 {
+    return group.windows(2).map(|w| (w[1] - w[0]).abs()).sum();
+}
+
+// Fractions of regular and singular groups under mask `mask`.
+fn rs_counts(plane: &Plane, mask: &[i32; 4], invert: bool) -> (f64, f64) {
+    let mut regular = 0usize;
+    let mut singular = 0usize;
+    let mut total = 0usize;
+    for row in plane.rows() {
+        for group in row.chunks_exact(4) {
+            let group: Vec<i32> = group.iter().map(|&x| if invert { x ^ 1 } else { x }).collect();
+            let flipped: Vec<i32> = group.iter().zip(mask).map(|(&x, &m)| flip(x, m)).collect();
+            let (before, after) = (smoothness(&group), smoothness(&flipped));
+            if after > before {
+                regular += 1;
+            } else if after < before {
+                singular += 1;
+            }
+            total += 1;
+        }
+    }
+    if total == 0 {
+        return (0.0, 0.0);
+    }
+    return (regular as f64 / total as f64, singular as f64 / total as f64);
+}
+
+fn rs(plane: &Plane) -> f64 {
+    let mask = [0, 1, 1, 0];
+    let negative = [0, -1, -1, 0];
+    let (rm, sm) = rs_counts(plane, &mask, false);
+    let (rnm, snm) = rs_counts(plane, &negative, false);
+    let (rm1, sm1) = rs_counts(plane, &mask, true);
+    let (rnm1, snm1) = rs_counts(plane, &negative, true);
+    let (d0, d1) = (rm - sm, rm1 - sm1);
+    let (dn0, dn1) = (rnm - snm, rnm1 - snm1);
+    let a = 2.0 * (d1 + d0);
+    let b = dn0 - dn1 - d1 - 3.0 * d0;
+    let c = d0 - dn0;
+    let x = match smaller_root(a, b, c) {
+        Some(x) => x,
+        None => return 0.0,
+    };
+    return clamp_rate(x / (x - 0.5));
+}
+
+fn spa(plane: &Plane) -> f64 {
+    let (mut x, mut y, mut z, mut w, mut total) = (0f64, 0f64, 0f64, 0f64, 0f64);
+    for row in plane.rows() {
+        for pair in row.windows(2) {
+            let (u, v) = (pair[0], pair[1]);
+            total += 1.0;
+            if u == v {
+                z += 1.0;
+                continue;
+            }
+            if (v % 2 == 0 && u < v) || (v % 2 == 1 && u > v) {
+                x += 1.0;
+            } else {
+                y += 1.0;
+            }
+            if u >> 1 == v >> 1 {
+                w += 1.0;
+            }
+        }
+    }
+    if total == 0.0 {
+        return 0.0;
+    }
+    return match smaller_root((w + z) / 2.0, 2.0 * x - total, y - x) {
+        Some(p) => clamp_rate(p),
+        None => 0.0,
+    };
+}
+
+fn smaller_root(a: f64, b: f64, c: f64) -> Option<f64> {
+    if a.abs() < 1e-12 {
+        return if b.abs() < 1e-12 { None } else { Some(-c / b) };
+    }
+    let disc = b * b - 4.0 * a * c;
+    if disc < 0.0 {
+        return Some(-b / (2.0 * a));
+    }
+    let r1 = (-b + disc.sqrt()) / (2.0 * a);
+    let r2 = (-b - disc.sqrt()) / (2.0 * a);
+    return Some(if r1.abs() < r2.abs() { r1 } else { r2 });
+}
+
+fn clamp_rate(rate: f64) -> f64 {
+    return if rate.is_finite() { rate.clamp(0.0, 1.0) } else { 0.0 };
+}
+
+fn ln_gamma(x: f64) -> f64 {
+    // Lanczos approximation, g = 7
+    const COEFFS: [f64; 9] = [
+        0.999_999_999_999_809_9, 676.520_368_121_885_1, -1_259.139_216_722_402_8,
+        771.323_428_777_653_1, -176.615_029_162_140_6, 12.507_343_278_686_905,
+        -0.138_571_095_265_720_12, 9.984_369_578_019_572e-6, 1.505_632_735_149_311_6e-7,
+    ];
+    if x < 0.5 {
+        return (std::f64::consts::PI / (std::f64::consts::PI * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let mut sum = COEFFS[0];
+    for (i, c) in COEFFS.iter().enumerate().skip(1) {
+        sum += c / (x + i as f64);
+    }
+    let t = x + 7.5;
+    return 0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln();
+}
+
+// Regularized lower incomplete gamma function P(a, x).
+fn regularized_gamma(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    let ln_prefix = a * x.ln() - x - ln_gamma(a);
+    if x < a + 1.0 {
+        let mut term = 1.0 / a;
+        let mut sum = term;
+        for n in 1..500 {
+            term *= x / (a + n as f64);
+            sum += term;
+            if term.abs() < sum.abs() * 1e-14 {
+                break;
+            }
+        }
+        return (sum * ln_prefix.exp()).min(1.0);
+    }
+    // continued fraction for the upper function, modified Lentz
+    let tiny = 1e-300;
+    let mut b = x + 1.0 - a;
+    let mut c = 1.0 / tiny;
+    let mut d = 1.0 / b;
+    let mut h = d;
+    for i in 1..500 {
+        let an = -(i as f64) * (i as f64 - a);
+        b += 2.0;
+        d = an * d + b;
+        if d.abs() < tiny {
+            d = tiny;
+        }
+        c = b + an / c;
+        if c.abs() < tiny {
+            c = tiny;
+        }
+        d = 1.0 / d;
+        let delta = d * c;
+        h *= delta;
+        if (delta - 1.0).abs() < 1e-14 {
+            break;
+        }
+    }
+    return (1.0 - ln_prefix.exp() * h).max(0.0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A smooth surface with some sensor-like noise, and the same with the
+    // LSB of a `rate` fraction of samples replaced by random bits, either the
+    // leading ones or spread over the whole plane.
+    fn testing_plane(rate: f64, spread: bool) -> Plane {
+        let (width, height) = (128, 128);
+        let mut seed: u64 = 0x2545f4914f6cdd1d;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+        let mut values: Vec<i32> = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let base = 60.0 + 50.0 * ((x as f64) / 17.0).sin() + 40.0 * ((y as f64) / 23.0).cos();
+                let noise = (0..3).map(|_| (next() % 7) as f64).sum::<f64>() - 9.0;
+                values.push((base + noise).round().clamp(0.0, 255.0) as i32);
+            }
+        }
+        let embedded = (values.len() as f64 * rate) as usize;
+        for (i, v) in values.iter_mut().enumerate() {
+            let chosen = if spread { (next() % 1000) as f64 / 1000.0 < rate } else { i < embedded };
+            if chosen {
+                *v = (*v & !1) | (next() & 1) as i32;
+            }
+        }
+        Plane { width, values }
+    }
+
+    #[test]
+    fn test_clean_plane() {
+        let plane = testing_plane(0.0, true);
+        assert!(rs(&plane) < 0.1, "rs {}", rs(&plane));
+        assert!(spa(&plane) < 0.1, "spa {}", spa(&plane));
+        assert!(chi_square(&plane).0 < 0.5);
+    }
+
+    #[test]
+    fn test_full_embedding() {
+        let plane = testing_plane(1.0, true);
+        assert!(rs(&plane) > 0.8, "rs {}", rs(&plane));
+        assert!(spa(&plane) > 0.8, "spa {}", spa(&plane));
+        let (p, rate) = chi_square(&plane);
+        assert!(p > 0.9);
+        assert_eq!(rate, 1.0);
+    }
+
+    #[test]
+    fn test_partial_embedding() {
+        let plane = testing_plane(0.5, true);
+        assert!((rs(&plane) - 0.5).abs() < 0.15, "rs {}", rs(&plane));
+        assert!((spa(&plane) - 0.5).abs() < 0.15, "spa {}", spa(&plane));
+        let (_, rate) = chi_square(&testing_plane(0.5, false));
+        assert!((0.3..=0.6).contains(&rate), "chi2 rate {}", rate);
+    }
+
+    #[test]
+    fn test_regularized_gamma() {
+        // P(1, x) = 1 - e^-x
+        assert!((regularized_gamma(1.0, 2.0) - (1.0 - (-2.0f64).exp())).abs() < 1e-10);
+        assert!((regularized_gamma(1.0, 0.5) - (1.0 - (-0.5f64).exp())).abs() < 1e-10);
+        assert!((ln_gamma(5.0) - 24f64.ln()).abs() < 1e-10);
+    }
+}
@@ -0,0 +1,179 @@
+//
+// author: xigang zhao
+//
+
+// Textual metadata chunks (tEXt, zTXt, iTXt) as described in the PNG spec.
+
+use std::convert::TryFrom;
+use std::str::FromStr;
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png_error::PngError;
+use crate::zlib;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextKind {
+    Text,          // tEXt
+    Compressed,    // zTXt
+    International, // iTXt
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct TextChunk {
+    kind    : TextKind,
+    keyword : String,
+    text    : String,
+}
+
+impl TextKind {
+    pub fn from_chunk_type(chunktype: &ChunkType) -> Option<TextKind> {
+        match &chunktype.bytes() {
+            b"tEXt" => Some(TextKind::Text),
+            b"zTXt" => Some(TextKind::Compressed),
+            b"iTXt" => Some(TextKind::International),
+            _ => None,
+        }
+    }
+
+    pub fn chunk_type(&self) -> ChunkType {
+        let code = match self {
+            TextKind::Text          => "tEXt",
+            TextKind::Compressed    => "zTXt",
+            TextKind::International => "iTXt",
+        };
+        return ChunkType::from_str(code).unwrap();
+    }
+}
+
+impl TryFrom<&Chunk> for TextChunk {
+    type Error = PngError;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let kind = match TextKind::from_chunk_type(chunk.chunk_type()) {
+            Some(kind) => kind,
+            None => return Err(PngError::InvalidTextChunk),
+        };
+        let (keyword, rest) = split_null(chunk.data())?;
+        let keyword = latin1_to_string(keyword);
+        let text = match kind {
+            TextKind::Text => latin1_to_string(rest),
+            TextKind::Compressed => {
+                let (method, rest) = rest.split_first().ok_or(PngError::InvalidTextChunk)?;
+                if *method != 0 {
+                    return Err(PngError::InvalidTextChunk);
+                }
+                latin1_to_string(&inflate(rest)?)
+            }
+            TextKind::International => {
+                if rest.len() < 2 {
+                    return Err(PngError::InvalidTextChunk);
+                }
+                let (flags, rest) = rest.split_at(2);
+                let (_language, rest) = split_null(rest)?;
+                let (_translated, rest) = split_null(rest)?;
+                let raw = match flags {
+                    [0, _] => rest.to_vec(),
+                    [1, 0] => inflate(rest)?,
+                    _ => return Err(PngError::InvalidTextChunk),
+                };
+                String::from_utf8(raw).map_err(|_| PngError::InvalidTextChunk)?
+            }
+        };
+        return Ok(TextChunk { kind, keyword, text });
+    }
+}
+
+impl TextChunk {
+    pub const KEYWORD_MAX: usize = 79;
+
+    pub fn new(kind: TextKind, keyword: &str, text: &str) -> Result<TextChunk, PngError> {
+        let valid_keyword = !keyword.is_empty()
+                            && keyword.len() <= TextChunk::KEYWORD_MAX
+                            && !keyword.starts_with(' ')
+                            && !keyword.ends_with(' ')
+                            && keyword.bytes().all(|b| (32..=126).contains(&b));
+        if !valid_keyword {
+            return Err(PngError::InvalidKeyword);
+        }
+        if kind != TextKind::International && !text.is_ascii() {
+            return Err(PngError::InvalidTextChunk);
+        }
+        return Ok(TextChunk { kind, keyword: keyword.to_string(), text: text.to_string() });
+    }
+
+    pub fn keyword(&self) -> &str {
+        return &self.keyword;
+    }
+
+    pub fn text(&self) -> &str {
+        return &self.text;
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data: Vec<u8> = self.keyword.as_bytes().to_vec();
+        data.push(0);
+        match self.kind {
+            TextKind::Text => data.extend_from_slice(self.text.as_bytes()),
+            TextKind::Compressed => {
+                data.push(0);
+                data.extend_from_slice(&zlib::deflate(self.text.as_bytes()));
+            }
+            TextKind::International => {
+                // uncompressed, no language tag or translated keyword
+                data.extend_from_slice(&[0, 0, 0, 0]);
+                data.extend_from_slice(self.text.as_bytes());
+            }
+        }
+        return Chunk::new(self.kind.chunk_type(), data);
+    }
+}
+
+fn split_null(data: &[u8]) -> Result<(&[u8], &[u8]), PngError> {
+    match data.iter().position(|&b| b == 0) {
+        Some(idx) => Ok((&data[..idx], &data[idx + 1..])),
+        None => Err(PngError::InvalidTextChunk),
+    }
+}
+
+fn latin1_to_string(data: &[u8]) -> String {
+    return data.iter().map(|&b| b as char).collect();
+}
+
+fn inflate(data: &[u8]) -> Result<Vec<u8>, PngError> {
+    return zlib::inflate(data).map_err(|_| PngError::InvalidTextChunk);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_round_trip() {
+        for kind in [TextKind::Text, TextKind::Compressed, TextKind::International] {
+            let tc = TextChunk::new(kind, "Comment", "Taken on a sunny afternoon.").unwrap();
+            let chunk = tc.to_chunk();
+            let parsed = TextChunk::try_from(&chunk).unwrap();
+            assert_eq!(parsed, tc);
+        }
+    }
+
+    #[test]
+    fn test_chunk_types() {
+        let tc = TextChunk::new(TextKind::Compressed, "Software", "x").unwrap();
+        assert_eq!(tc.to_chunk().chunk_type().to_string(), "zTXt");
+        let tc = TextChunk::new(TextKind::International, "Software", "x").unwrap();
+        assert_eq!(tc.to_chunk().chunk_type().to_string(), "iTXt");
+    }
+
+    #[test]
+    fn test_invalid_keyword() {
+        assert!(TextChunk::new(TextKind::Text, "", "x").is_err());
+        assert!(TextChunk::new(TextKind::Text, " Comment", "x").is_err());
+        assert!(TextChunk::new(TextKind::Text, &"k".repeat(80), "x").is_err());
+    }
+
+    #[test]
+    fn test_not_a_text_chunk() {
+        let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"Comment\0hi".to_vec());
+        assert!(TextChunk::try_from(&chunk).is_err());
+    }
+}
@@ -0,0 +1,94 @@
+//
+// author: xigang zhao
+//
+
+use std::io::Write;
+use flate2::{Compression, Decompress, FlushDecompress, Status};
+use flate2::write::ZlibEncoder;
+use crate::png_error::PngError;
+
+// the most any stream may inflate to unless the caller knows better
+pub const MAX_INFLATED: usize = 1 << 30;
+
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    return deflate_with(data, Compression::default());
+}
+
+pub fn deflate_with(data: &[u8], level: Compression) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), level);
+    encoder.write_all(data).unwrap();
+    return encoder.finish().unwrap();
+}
+
+pub fn inflate(data: &[u8]) -> Result<Vec<u8>, PngError> {
+    return Ok(inflate_stream(data, MAX_INFLATED)?.0);
+}
+
+// Inflates one zlib stream and also returns how many input bytes it used, so
+// callers can spot data hidden after the end of the stream. A stream that
+// inflates to more than `limit` bytes is an error.
+pub fn inflate_stream(data: &[u8], limit: usize) -> Result<(Vec<u8>, usize), PngError> {
+    let mut decoder = Decompress::new(true);
+    let mut out: Vec<u8> = Vec::with_capacity(data.len().saturating_mul(4).min(limit.saturating_add(1)));
+    loop {
+        if out.len() > limit {
+            return Err(PngError::InflatedTooLarge(limit));
+        }
+        if out.len() == out.capacity() {
+            // one byte past the limit is enough to tell it was exceeded
+            out.reserve_exact(out.len().max(1024).min(limit + 1 - out.len()));
+        }
+        let consumed = decoder.total_in() as usize;
+        let status = decoder.decompress_vec(&data[consumed..], &mut out, FlushDecompress::None)
+                            .map_err(|_| PngError::InvalidCompressedData)?;
+        match status {
+            Status::StreamEnd => break,
+            Status::Ok => {}
+            Status::BufError => {
+                // no progress possible with output space left: input ran out
+                if out.len() < out.capacity() {
+                    return Err(PngError::InvalidCompressedData);
+                }
+            }
+        }
+    }
+    if out.len() > limit {
+        return Err(PngError::InflatedTooLarge(limit));
+    }
+    return Ok((out, decoder.total_in() as usize));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let data: Vec<u8> = (0..10000u32).map(|i| (i % 251) as u8).collect();
+        assert_eq!(inflate(&deflate(&data)).unwrap(), data);
+    }
+
+    #[test]
+    fn test_extra_bytes() {
+        let mut stream = deflate(b"hello");
+        let used = stream.len();
+        stream.extend_from_slice(b"hidden");
+        let (out, consumed) = inflate_stream(&stream, MAX_INFLATED).unwrap();
+        assert_eq!(out, b"hello");
+        assert_eq!(consumed, used);
+    }
+
+    #[test]
+    fn test_limit() {
+        let bomb = deflate(&vec![0; 1 << 20]);
+        assert_eq!(inflate_stream(&bomb, 1 << 20).unwrap().0.len(), 1 << 20);
+        assert!(matches!(inflate_stream(&bomb, (1 << 20) - 1), Err(PngError::InflatedTooLarge(_))));
+        assert!(matches!(inflate_stream(&bomb, 0), Err(PngError::InflatedTooLarge(0))));
+    }
+
+    #[test]
+    fn test_truncated() {
+        let stream = deflate(b"hello world, hello world");
+        assert!(inflate(&stream[..stream.len() - 6]).is_err());
+    }
+}